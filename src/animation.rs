@@ -0,0 +1,170 @@
+use glam::{Mat4, Quat, Vec3};
+use gltf::animation::Interpolation;
+
+use crate::structs::Transform;
+
+/// The part of a node that an animation channel changes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+    /// The weights of the morph targets, with the number of targets.
+    Weights(usize),
+}
+
+/// The keyframes of a single property of a single node.
+pub struct Channel {
+    pub node: usize,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    /// Time of each keyframe, in seconds.
+    pub times: Vec<f32>,
+    /// The values of the keyframes, one after the other. Cubic spline keyframes store an
+    /// in-tangent, the value and an out-tangent for every keyframe.
+    pub values: Vec<f32>,
+}
+
+/// An animation clip from the glTF file.
+pub struct Animation {
+    pub name: String,
+    pub channels: Vec<Channel>,
+}
+
+impl Property {
+    /// Number of floats in a single value of this property.
+    pub fn components(&self) -> usize {
+        match self {
+            Property::Translation | Property::Scale => 3,
+            Property::Rotation => 4,
+            Property::Weights(n_targets) => *n_targets,
+        }
+    }
+}
+
+impl Animation {
+    /// Length of the clip in seconds, which is the time of the last keyframe of any channel.
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0, |duration, time| duration.max(*time))
+    }
+
+    /// Number of frames needed to play the whole clip at `frame_rate` frames per second, including the first and last frame.
+    pub fn frame_count(&self, frame_rate: f32) -> usize {
+        (self.duration() * frame_rate).ceil() as usize + 1
+    }
+
+    /// The nodes this clip moves, in glTF node order. Nodes that only have their morph target weights animated aren't included.
+    pub fn nodes(&self) -> Vec<usize> {
+        let mut nodes: Vec<usize> = self
+            .channels
+            .iter()
+            .filter(|channel| !matches!(channel.property, Property::Weights(_)))
+            .map(|channel| channel.node)
+            .collect();
+        nodes.sort();
+        nodes.dedup();
+        nodes
+    }
+
+    /// The local transform of `node` at `time` seconds. Properties that aren't animated keep the value from `rest`.
+    pub fn sample_node(&self, node: usize, rest: &Transform, time: f32) -> Mat4 {
+        let mut translation = rest.translation;
+        let mut rotation = rest.rotation;
+        let mut scale = rest.scale;
+        for channel in self.channels.iter().filter(|channel| channel.node == node) {
+            let value = channel.sample(time);
+            match channel.property {
+                Property::Translation => translation = Vec3::from_slice(&value),
+                Property::Rotation => rotation = Quat::from_slice(&value).normalize(),
+                Property::Scale => scale = Vec3::from_slice(&value),
+                Property::Weights(_) => {}
+            }
+        }
+        Mat4::from_scale_rotation_translation(scale, rotation, translation)
+    }
+
+    /// Returns true if this clip animates the morph target weights of `node`.
+    pub fn animates_weights(&self, node: usize) -> bool {
+        self.channels
+            .iter()
+            .any(|channel| channel.node == node && matches!(channel.property, Property::Weights(_)))
+    }
+
+    /// The morph target weights of `node` at `time` seconds, or `rest` if they aren't animated.
+    pub fn sample_weights(&self, node: usize, rest: &[f32], time: f32) -> Vec<f32> {
+        self.channels
+            .iter()
+            .rfind(|channel| {
+                channel.node == node && matches!(channel.property, Property::Weights(_))
+            })
+            .map_or_else(|| rest.to_vec(), |channel| channel.sample(time))
+    }
+}
+
+impl Channel {
+    /// The value of this channel at `time` seconds. Before the first keyframe this is the first value,
+    /// and after the last keyframe the last value.
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        let n = self.property.components();
+        let cubic = self.interpolation == Interpolation::CubicSpline;
+
+        // Cubic splines have an in-tangent before each value
+        let value = |key: usize| -> &[f32] {
+            let start = match cubic {
+                true => (key * 3 + 1) * n,
+                false => key * n,
+            };
+            &self.values[start..start + n]
+        };
+
+        // Find the keyframes on either side of the time
+        let next = self.times.partition_point(|key_time| *key_time <= time);
+        if next == 0 {
+            return value(0).to_vec();
+        }
+        if next == self.times.len() {
+            return value(next - 1).to_vec();
+        }
+        let previous = next - 1;
+        let delta = self.times[next] - self.times[previous];
+        let t = match delta > 0.0 {
+            true => (time - self.times[previous]) / delta,
+            false => 0.0,
+        };
+
+        match self.interpolation {
+            Interpolation::Step => value(previous).to_vec(),
+            Interpolation::Linear if self.property == Property::Rotation => {
+                let a = Quat::from_slice(value(previous));
+                let b = Quat::from_slice(value(next));
+                a.slerp(b, t).to_array().to_vec()
+            }
+            Interpolation::Linear => value(previous)
+                .iter()
+                .zip(value(next))
+                .map(|(a, b)| a + (b - a) * t)
+                .collect(),
+            Interpolation::CubicSpline => {
+                // Hermite spline, with the out-tangent of the previous keyframe and the in-tangent of the next one
+                let out_tangent = &self.values[(previous * 3 + 2) * n..(previous * 3 + 3) * n];
+                let in_tangent = &self.values[(next * 3) * n..(next * 3 + 1) * n];
+                let (t2, t3) = (t * t, t * t * t);
+                let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+                let h10 = t3 - 2.0 * t2 + t;
+                let h01 = -2.0 * t3 + 3.0 * t2;
+                let h11 = t3 - t2;
+                (0..n)
+                    .map(|i| {
+                        h00 * value(previous)[i]
+                            + h10 * delta * out_tangent[i]
+                            + h01 * value(next)[i]
+                            + h11 * delta * in_tangent[i]
+                    })
+                    .collect()
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::str::FromStr;
+
+use glam::{Mat3, Vec3};
+
+/// Describes where each output axis comes from. glTF is always Y-up and right-handed, but
+/// the game might want something else.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AxisMapping {
+    /// For each output axis: the glTF axis it takes its value from (0 = X, 1 = Y, 2 = Z),
+    /// and whether that value gets negated.
+    pub axes: [(usize, bool); 3],
+}
+
+impl AxisMapping {
+    /// Y pointing down, like the PS1 GTE expects. This is glTF rotated 180 degrees around Z.
+    pub const Y_DOWN: AxisMapping = AxisMapping {
+        axes: [(0, true), (1, true), (2, false)],
+    };
+
+    /// Y-up and right-handed, the same as glTF.
+    pub const Y_UP: AxisMapping = AxisMapping {
+        axes: [(0, false), (1, false), (2, false)],
+    };
+
+    /// Z-up and right-handed, like Blender.
+    pub const Z_UP: AxisMapping = AxisMapping {
+        axes: [(0, false), (2, true), (1, false)],
+    };
+
+    /// The matrix that transforms a glTF position to the output space.
+    pub fn to_mat3(&self) -> Mat3 {
+        let mut columns = [Vec3::ZERO; 3];
+        for (output_axis, (source_axis, negate)) in self.axes.iter().enumerate() {
+            columns[*source_axis][output_axis] = if *negate { -1.0 } else { 1.0 };
+        }
+        Mat3::from_cols(columns[0], columns[1], columns[2])
+    }
+
+    /// Whether this mapping mirrors the model, which turns the winding order of every triangle around.
+    pub fn flips_handedness(&self) -> bool {
+        self.to_mat3().determinant() < 0.0
+    }
+}
+
+impl Default for AxisMapping {
+    fn default() -> Self {
+        AxisMapping::Y_DOWN
+    }
+}
+
+impl FromStr for AxisMapping {
+    type Err = String;
+
+    /// Parse either a preset ("y-down", "y-up", "z-up"), or three comma separated axes like "-x,-y,+z".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "y-down" => return Ok(AxisMapping::Y_DOWN),
+            "y-up" => return Ok(AxisMapping::Y_UP),
+            "z-up" => return Ok(AxisMapping::Z_UP),
+            _ => {}
+        }
+
+        let mut axes = [(0, false); 3];
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        if parts.len() != 3 {
+            return Err(format!(
+                "invalid axis mapping '{s}', expected a preset or 3 comma separated axes like '-x,-y,+z'"
+            ));
+        }
+        for (axis, part) in axes.iter_mut().zip(parts) {
+            let (negate, name) = match part.strip_prefix('-') {
+                Some(name) => (true, name),
+                None => (false, part.strip_prefix('+').unwrap_or(part)),
+            };
+            let source_axis = match name {
+                "x" | "X" => 0,
+                "y" | "Y" => 1,
+                "z" | "Z" => 2,
+                _ => return Err(format!("invalid axis '{part}' in axis mapping '{s}'")),
+            };
+            *axis = (source_axis, negate);
+        }
+
+        // Every glTF axis has to be used exactly once
+        let mut used = [false; 3];
+        for (source_axis, _) in axes {
+            used[source_axis] = true;
+        }
+        if used.contains(&false) {
+            return Err(format!(
+                "invalid axis mapping '{s}', every axis has to be used once"
+            ));
+        }
+
+        Ok(AxisMapping { axes })
+    }
+}
@@ -0,0 +1,1112 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use exoquant::{convert_to_indexed, ditherer, optimizer, Color};
+use glam::{Mat3, Mat4, Vec3};
+use image::{DynamicImage, RgbaImage};
+use serde_json::{Map, Value};
+
+use crate::{
+    axes::AxisMapping,
+    error::ConvertError,
+    helpers::matches_pattern,
+    mesh::{Bone, Instance, Model, Morph},
+    partition::{partition_triangles, Partition, MAX_OCTREE_DEPTH},
+    psx_structs::{
+        name_hash, AnimationCollectionPSX, BonePSX, ClipPSX, EntityCollectionPSX, EntityPSX,
+        EntityPropertyPSX, InstancePSX, MeshPSX, ModelPSX, MorphSequencePSX, PropertyValue,
+        SkeletonPSX, TextureCellPSX, TextureCollectionPSX, TrackPSX, TransformPSX,
+        VertexAnimationPSX, VertexPSX,
+    },
+    scene::{find_scene, scene_file_name, NodeFilter, SceneRef},
+    structs::Vertex,
+    texture::Material,
+};
+
+/// Settings that control how a glTF model is converted to the PSX formats.
+pub struct ConvertOptions {
+    /// Which scene to convert. By default this is the default scene, or the first scene if there's no default.
+    pub scene: Option<SceneRef>,
+    /// How to split the model into submeshes.
+    pub partition: Partition,
+    /// Number of fixed-point units per glTF unit.
+    pub scale: Scale,
+    /// Which way the axes of the output point.
+    pub axes: AxisMapping,
+    /// Store the vertex positions of each submesh relative to its center, instead of the model origin.
+    pub relative_origins: bool,
+    /// Nodes with a name that matches one of these patterns are kept as their own submesh, together with
+    /// their children, instead of being partitioned. `*` and `?` can be used as wildcards.
+    pub keep_nodes: Vec<String>,
+    /// Which nodes to convert, by name pattern.
+    pub node_filter: NodeFilter,
+    /// Convert each glTF mesh once in its own local space, and store the nodes that use it in an instance table,
+    /// instead of baking every node into the model. Partitioning and `keep_nodes` don't apply in this mode.
+    pub instancing: bool,
+    /// Extras keys of mesh-less nodes that get exported to the entity file. `*` and `?` can be used as
+    /// wildcards. Every key is exported if this is empty.
+    pub entity_properties: Vec<String>,
+    /// Number of frames per second the animations get resampled at.
+    pub frame_rate: f32,
+    /// Number of colors to quantize each texture to. Textures are stored as 4 bit, so at most 16.
+    pub palette_size: usize,
+    /// Dithering used while quantizing the textures.
+    pub dither: Dither,
+    /// Export each material's source texture as a PNG in the working directory.
+    pub debug_view: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scale {
+    /// Use this many fixed-point units per glTF unit.
+    Fixed(f32),
+    /// Pick the largest scale that still fits the whole model in the 16-bit vertex positions.
+    AutoFit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dither {
+    None,
+    Ordered,
+    FloydSteinberg,
+}
+
+/// The in-memory result of a conversion, ready to be saved or inspected.
+pub struct ConvertOutput {
+    pub model: ModelPSX,
+    pub textures: TextureCollectionPSX,
+    /// The nodes without a mesh, like spawn points and triggers.
+    pub entities: EntityCollectionPSX,
+    /// The bones of the skinned meshes in the model. Empty if there aren't any.
+    pub skeleton: SkeletonPSX,
+    /// The animation clips of the scene. Empty if there aren't any.
+    pub animations: AnimationCollectionPSX,
+    /// The morph targets of the model, and the clips that animate their weights. Empty if there aren't any.
+    pub vertex_animations: VertexAnimationPSX,
+    /// Problems that didn't stop the conversion, but probably need a look.
+    pub warnings: Vec<String>,
+}
+
+/// What a submesh was made for, so the instances, bones and morph targets can find it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum SubmeshOwner {
+    /// A kept node, a node with morph targets, or the node a mesh in local space was converted for.
+    Node(usize),
+    /// The skinned triangles of the bone on this node.
+    Bone(usize),
+}
+
+impl SubmeshOwner {
+    fn node(&self) -> usize {
+        match self {
+            SubmeshOwner::Node(node) | SubmeshOwner::Bone(node) => *node,
+        }
+    }
+}
+
+/// A triangle waiting to be put in a submesh.
+struct Triangle {
+    verts: [Vertex; 3],
+    texture_id: u8,
+    node: usize,
+    /// Rigidly skinned triangles are in the local space of the bone on `node`.
+    skinned: bool,
+    /// The morph target offsets of each vertex, if its node has any.
+    morphs: [Vec<Vec3>; 3],
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions {
+            scene: None,
+            partition: Partition::Grid(Vec3::new(1.75, 50000.0, 1.75)),
+            scale: Scale::Fixed(1024.0),
+            axes: AxisMapping::Y_DOWN,
+            relative_origins: false,
+            keep_nodes: Vec::new(),
+            node_filter: NodeFilter::default(),
+            instancing: false,
+            entity_properties: Vec::new(),
+            frame_rate: 30.0,
+            palette_size: 16,
+            dither: Dither::Ordered,
+            debug_view: false,
+        }
+    }
+}
+
+impl FromStr for Scale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Scale::AutoFit),
+            _ => s
+                .parse::<f32>()
+                .map(Scale::Fixed)
+                .map_err(|_| format!("invalid scale '{s}', expected a number or 'auto'")),
+        }
+    }
+}
+
+impl FromStr for Dither {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Dither::None),
+            "ordered" => Ok(Dither::Ordered),
+            "floyd-steinberg" => Ok(Dither::FloydSteinberg),
+            _ => Err(format!(
+                "unknown dithering mode '{s}', expected 'none', 'ordered' or 'floyd-steinberg'"
+            )),
+        }
+    }
+}
+
+/// Load a glTF file from disk and convert it to a PSX model and texture collection.
+pub fn convert_gltf(path: &Path, options: &ConvertOptions) -> Result<ConvertOutput, ConvertError> {
+    let mut model = Model::new();
+    model.create_from_gltf(
+        path,
+        options.scene.as_ref(),
+        &options.node_filter,
+        options.instancing,
+    )?;
+    convert_model(model, options).map_err(|err| err.with_path(path))
+}
+
+/// Load a glTF file from disk and convert each of its scenes separately, ignoring `options.scene`.
+/// Returns the output of each scene, together with a name for the scene that can be used in a file name.
+pub fn convert_gltf_scenes(
+    path: &Path,
+    options: &ConvertOptions,
+) -> Result<Vec<(String, ConvertOutput)>, ConvertError> {
+    let (gltf_document, mesh_data, image_data) =
+        gltf::import(path).map_err(|err| ConvertError::from_gltf(path, err))?;
+
+    // A file without scenes fails the same way as when converting a single scene
+    find_scene(&gltf_document, None).map_err(|err| err.with_path(path))?;
+
+    let mut outputs: Vec<(String, ConvertOutput)> = Vec::new();
+    for scene in gltf_document.scenes() {
+        // Scenes can share a name, but they can't share a file
+        let mut name = scene_file_name(&scene);
+        if outputs.iter().any(|(other, _)| *other == name) {
+            name = format!("{name}_{}", scene.index());
+        }
+
+        let mut model = Model::new();
+        let output = model
+            .create_from_gltf_document(
+                &gltf_document,
+                &mesh_data,
+                &image_data,
+                Some(&scene),
+                &options.node_filter,
+                options.instancing,
+            )
+            .and_then(|_| convert_model(model, options))
+            .map_err(|err| err.with_path(path))?;
+        outputs.push((name, output));
+    }
+    Ok(outputs)
+}
+
+/// Convert a glTF file that is already in memory. External buffers and images can't be resolved this way.
+pub fn convert_gltf_slice(
+    bytes: &[u8],
+    options: &ConvertOptions,
+) -> Result<ConvertOutput, ConvertError> {
+    let mut model = Model::new();
+    model.create_from_gltf_slice(
+        bytes,
+        options.scene.as_ref(),
+        &options.node_filter,
+        options.instancing,
+    )?;
+    convert_model(model, options)
+}
+
+/// Write the files of a converted model: `<base_path>.msh`, `<base_path>.txc` and `<base_path>.ent`.
+/// Skinned models also get a `<base_path>.skl`, animated scenes a `<base_path>.anm`, and models with morph targets a `<base_path>.vta`.
+pub fn save_outputs(base_path: &Path, output: &ConvertOutput) -> Result<(), ConvertError> {
+    output.model.save(&with_suffix(base_path, ".msh"))?;
+    output.textures.save(&with_suffix(base_path, ".txc"))?;
+    output.entities.save(&with_suffix(base_path, ".ent"))?;
+    if !output.skeleton.bones.is_empty() {
+        output.skeleton.save(&with_suffix(base_path, ".skl"))?;
+    }
+    if !output.animations.clips.is_empty() {
+        output.animations.save(&with_suffix(base_path, ".anm"))?;
+    }
+    if !output.vertex_animations.sequences.is_empty() {
+        output
+            .vertex_animations
+            .save(&with_suffix(base_path, ".vta"))?;
+    }
+    Ok(())
+}
+
+/// Convert a glTF file and write its files next to `path_out`, see `save_outputs`.
+/// Returns the converted data, which includes any warnings.
+pub fn export_msh(
+    path_in: &Path,
+    path_out: &Path,
+    options: &ConvertOptions,
+) -> Result<ConvertOutput, ConvertError> {
+    let output = convert_gltf(path_in, options)?;
+    save_outputs(path_out, &output)?;
+    Ok(output)
+}
+
+/// Convert every scene in a glTF file, and write the files of each of them to `<path_out>_<scene>`, see `save_outputs`.
+/// Returns the converted data of each scene, which includes any warnings.
+pub fn export_scenes(
+    path_in: &Path,
+    path_out: &Path,
+    options: &ConvertOptions,
+) -> Result<Vec<(String, ConvertOutput)>, ConvertError> {
+    let outputs = convert_gltf_scenes(path_in, options)?;
+    for (name, output) in &outputs {
+        save_outputs(&with_suffix(path_out, &format!("_{name}")), output)?;
+    }
+    Ok(outputs)
+}
+
+/// Returns true if the path has a file extension the glTF importer understands (.gltf or .glb).
+pub fn is_gltf_path(path: &Path) -> bool {
+    matches!(
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase())
+            .as_deref(),
+        Some("gltf") | Some("glb")
+    )
+}
+
+/// Find the path (without extension) to write the output files of `path_in` to.
+/// By default the output goes next to the input file and takes its name, so `level.glb` becomes `level`.
+pub fn output_base_path(path_in: &Path, out_dir: Option<&Path>, name: Option<&str>) -> PathBuf {
+    let out_dir = out_dir
+        .or_else(|| path_in.parent())
+        .unwrap_or(Path::new(""));
+    match name {
+        Some(name) => out_dir.join(name),
+        None => out_dir.join(path_in.file_stem().unwrap_or("model".as_ref())),
+    }
+}
+
+/// Append a suffix to a path without touching any dots that are already in the file name.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Find the largest scale that keeps every vertex of the model inside the 16-bit range,
+/// rounded down to the 16.16 fixed-point precision it gets stored with.
+pub fn auto_fit_scale(model: &Model) -> f32 {
+    // The axis mapping only swaps and negates axes, so we only need the largest absolute coordinate
+    let mut max_extent = 0.0f32;
+    for mesh in model.meshes.values() {
+        for vertex in &mesh.verts {
+            max_extent = max_extent.max(vertex.position.abs().max_element());
+        }
+    }
+
+    // An empty or flat model fits at any scale
+    if max_extent <= 0.0 {
+        return 1024.0;
+    }
+    let scale = (32767.0 / max_extent).min(65535.0);
+    (scale * 65536.0).floor() / 65536.0
+}
+
+/// Split the triangles into groups that each become a submesh. Triangles that belong to a bone or a kept node
+/// get a group for that node, the rest is partitioned. Nodes with morph targets are always kept, so their
+/// vertices stay together, and so are animated nodes, whose mesh is in local space. Returns the bone or kept
+/// node of each group, if any.
+fn group_triangles(
+    triangles: &[Triangle],
+    centroids: &[Vec3],
+    node_names: &[String],
+    node_parents: &[Option<usize>],
+    bones: &[Bone],
+    morphs: &[Morph],
+    instances: &[Instance],
+    options: &ConvertOptions,
+) -> Vec<(Option<SubmeshOwner>, Vec<usize>)> {
+    // Find the kept node each node belongs to: either itself, or the closest parent that's kept
+    let is_kept = |node: usize| {
+        morphs.iter().any(|morph| morph.node == node)
+            || instances.iter().any(|instance| instance.node == node)
+            || options
+                .keep_nodes
+                .iter()
+                .any(|pattern| matches_pattern(pattern, &node_names[node]))
+    };
+    let object_of = |mut node: usize| loop {
+        if is_kept(node) {
+            return Some(node);
+        }
+        node = node_parents[node]?;
+    };
+
+    // Skinned triangles are tagged with their bone, and always stay with it. Other meshes on the node of
+    // a bone are in model space, so they're treated like any other node
+    let mut bone_groups = vec![Vec::new(); bones.len()];
+    let mut objects = BTreeMap::<usize, Vec<usize>>::new();
+    let mut rest = Vec::new();
+    for (index, triangle) in triangles.iter().enumerate() {
+        let bone = bones.iter().position(|bone| bone.node == triangle.node);
+        if let Some(bone) = bone.filter(|_| triangle.skinned) {
+            bone_groups[bone].push(index);
+            continue;
+        }
+        match object_of(triangle.node) {
+            Some(node) => objects.entry(node).or_default().push(index),
+            None => rest.push(index),
+        }
+    }
+
+    // Partition everything that's not kept, then add the kept nodes in glTF node order
+    let rest_centroids: Vec<Vec3> = rest.iter().map(|index| centroids[*index]).collect();
+    let mut groups: Vec<(Option<SubmeshOwner>, Vec<usize>)> =
+        partition_triangles(&rest_centroids, &options.partition)
+            .into_iter()
+            .map(|group| (None, group.iter().map(|index| rest[*index]).collect()))
+            .collect();
+    groups.extend(
+        objects
+            .into_iter()
+            .map(|(node, group)| (Some(SubmeshOwner::Node(node)), group)),
+    );
+
+    // Then the bones that have any triangles, parents first
+    groups.extend(
+        bones
+            .iter()
+            .zip(bone_groups)
+            .filter(|(_, group)| !group.is_empty())
+            .map(|(bone, group)| (Some(SubmeshOwner::Bone(bone.node)), group)),
+    );
+    groups
+}
+
+/// Split the triangles of an instanced model into one group per glTF mesh. The triangles of each mesh
+/// are tagged with the node it was converted for, so this groups them by node, in glTF node order.
+/// Skinned triangles get a group per bone, after the mesh of the same node.
+fn group_instanced_triangles(triangles: &[Triangle]) -> Vec<(SubmeshOwner, Vec<usize>)> {
+    let mut prototypes = BTreeMap::<(usize, bool), Vec<usize>>::new();
+    for (index, triangle) in triangles.iter().enumerate() {
+        prototypes
+            .entry((triangle.node, triangle.skinned))
+            .or_default()
+            .push(index);
+    }
+    prototypes
+        .into_iter()
+        .map(|((node, skinned), group)| match skinned {
+            true => (SubmeshOwner::Bone(node), group),
+            false => (SubmeshOwner::Node(node), group),
+        })
+        .collect()
+}
+
+/// The nodes from `node` up to, but not including, its parent node `base`, or up to the scene root if `base` is None.
+/// Combining their local transforms from last to first gives the transform of `node` relative to `base`.
+fn node_chain(node_parents: &[Option<usize>], node: usize, base: Option<usize>) -> Vec<usize> {
+    let mut chain = vec![node];
+    while let Some(parent) =
+        node_parents[*chain.last().unwrap()].filter(|parent| Some(*parent) != base)
+    {
+        chain.push(parent);
+    }
+    chain
+}
+
+/// Convert the transform of a node to the output axes and fixed point. `context` names the node in warnings.
+fn transform_to_psx(
+    transform: Mat4,
+    position_matrix: Mat3,
+    axes: &AxisMapping,
+    context: &str,
+    warnings: &mut Vec<String>,
+) -> TransformPSX {
+    let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+
+    // The rotation has to be rotated into the output space as well, and the axes of the scale get swapped around
+    let axes_matrix = axes.to_mat3();
+    let rotation = axes_matrix * Mat3::from_quat(rotation) * axes_matrix.transpose();
+    let scale = Vec3::new(
+        scale[axes.axes[0].0],
+        scale[axes.axes[1].0],
+        scale[axes.axes[2].0],
+    );
+    let position = (position_matrix * translation).round();
+
+    // 4.12 fixed point only goes up to just under 8
+    if scale.abs().max_element() * 4096.0 > i16::MAX as f32 {
+        warnings.push(format!(
+            "{context}: the scale {scale} doesn't fit in 4.12 fixed point and was clamped"
+        ));
+    }
+    if position.abs().max_element() > i32::MAX as f32 {
+        warnings.push(format!(
+            "{context}: the position doesn't fit in 32 bits and was clamped"
+        ));
+    }
+    let to_fixed = |value: f32| (value * 4096.0).round().clamp(-32768.0, 32767.0) as i16;
+
+    // glam matrices are column major, the file stores them row by row
+    let rows = rotation.transpose().to_cols_array();
+    TransformPSX {
+        position: [position.x as i32, position.y as i32, position.z as i32],
+        rotation: rows.map(to_fixed),
+        scale: [scale.x, scale.y, scale.z].map(to_fixed),
+    }
+}
+
+/// Convert the extras of an entity to typed properties. Only keys that match `patterns` are kept, or all of
+/// them if there are no patterns. Values that can't be stored are skipped with a warning.
+fn entity_properties(
+    extras: &Map<String, Value>,
+    patterns: &[String],
+    context: &str,
+    warnings: &mut Vec<String>,
+) -> Vec<EntityPropertyPSX> {
+    let mut properties = Vec::new();
+    for (key, value) in extras {
+        // `psx_export` is for the converter, not the game
+        let selected =
+            patterns.is_empty() || patterns.iter().any(|pattern| matches_pattern(pattern, key));
+        if key == "psx_export" || !selected {
+            continue;
+        }
+
+        // JSON integers are integers, and every float is stored as 20.12 fixed point, even a whole one like 2.0.
+        // That way a key always gets the same type, whatever its value is on each node
+        let value = match value {
+            Value::Bool(value) => PropertyValue::Bool(*value),
+            Value::Number(number) => match (number.as_i64(), number.as_f64()) {
+                (Some(value), _) => {
+                    if i32::try_from(value).is_err() {
+                        warnings.push(format!(
+                            "{context}: property '{key}' doesn't fit in 32 bits and was clamped"
+                        ));
+                    }
+                    PropertyValue::Int(value.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+                }
+                (None, Some(value)) => {
+                    let fixed = (value * 4096.0).round();
+                    if fixed < i32::MIN as f64 || fixed > i32::MAX as f64 {
+                        warnings.push(format!(
+                            "{context}: property '{key}' doesn't fit in 20.12 fixed point and was clamped"
+                        ));
+                    }
+                    PropertyValue::Fixed(fixed.clamp(i32::MIN as f64, i32::MAX as f64) as i32)
+                }
+                (None, None) => continue,
+            },
+            Value::String(value) => PropertyValue::String(value.clone()),
+            Value::Null | Value::Array(_) | Value::Object(_) => {
+                warnings.push(format!(
+                    "{context}: property '{key}' was skipped, only booleans, numbers and strings can be exported"
+                ));
+                continue;
+            }
+        };
+        properties.push(EntityPropertyPSX {
+            key: key.clone(),
+            value,
+        });
+    }
+    properties
+}
+
+/// Convert an already loaded model to the PSX formats.
+pub fn convert_model(
+    model: Model,
+    options: &ConvertOptions,
+) -> Result<ConvertOutput, ConvertError> {
+    // Prepare PSX output model
+    let mut model_psx_out = ModelPSX::new();
+    let mut txc_psx_out = TextureCollectionPSX::new();
+
+    // The textures are stored as 4 bit indices, so the palette can't be any bigger than that
+    if options.palette_size == 0 || options.palette_size > 16 {
+        return Err(ConvertError::unsupported(
+            "options",
+            format!(
+                "a palette size of {}, it must be between 1 and 16",
+                options.palette_size
+            ),
+        ));
+    }
+
+    // A grid cell needs a size, or every triangle ends up in a cell at infinity
+    match options.partition {
+        Partition::Grid(cell_size)
+            if !(cell_size.cmpgt(Vec3::ZERO).all() && cell_size.is_finite()) =>
+        {
+            return Err(ConvertError::unsupported(
+                "options",
+                format!("a grid size of {cell_size}, every axis must be above 0"),
+            ));
+        }
+        Partition::Octree { max_depth, .. } if max_depth > MAX_OCTREE_DEPTH => {
+            return Err(ConvertError::unsupported(
+                "options",
+                format!("an octree depth of {max_depth}, it must be at most {MAX_OCTREE_DEPTH}"),
+            ));
+        }
+        _ => {}
+    }
+
+    // Find the scale, and the matrix that takes glTF positions to fixed-point positions
+    let scale = match options.scale {
+        Scale::Fixed(scale) => scale,
+        Scale::AutoFit => auto_fit_scale(&model),
+    };
+    if !(scale > 0.0 && scale < 65536.0) {
+        return Err(ConvertError::unsupported(
+            "options",
+            format!("a scale of {scale}, it must be above 0 and below 65536"),
+        ));
+    }
+    let position_matrix = options.axes.to_mat3() * scale;
+    if !(options.frame_rate > 0.0 && options.frame_rate < 65536.0) {
+        return Err(ConvertError::unsupported(
+            "options",
+            format!(
+                "a frame rate of {}, it must be above 0 and below 65536",
+                options.frame_rate
+            ),
+        ));
+    }
+    let flip_winding = options.axes.flips_handedness();
+    model_psx_out.scale = scale;
+
+    // All triangles in the model, which get split into submeshes once we have all of them
+    let mut triangles = Vec::<Triangle>::new();
+    let mut centroids = Vec::<Vec3>::new();
+
+    // Loop over each submesh in the model
+    for (texture_id, (material_index, mesh)) in model.meshes.into_iter().enumerate() {
+        // Retrieve material corresponding to this submesh
+        let mat: &Material = model.materials.get(&material_index).ok_or_else(|| {
+            ConvertError::malformed(
+                format!("material {material_index:?}"),
+                "the material could not be found",
+            )
+        })?;
+
+        // The texture id has to fit in a single byte
+        let context = format!("material '{}'", mat.name);
+        if texture_id > u8::MAX as usize {
+            return Err(ConvertError::overflow(
+                context,
+                "the model uses more than 256 materials",
+            ));
+        }
+
+        // Collect the triangles, and remember where they are for the partitioning
+        for (((triangle, node), skinned), morphs) in mesh
+            .verts
+            .chunks(3)
+            .zip(&mesh.nodes)
+            .zip(&mesh.skinned)
+            .zip(mesh.morphs.chunks(3))
+        {
+            centroids
+                .push((triangle[0].position + triangle[1].position + triangle[2].position) / 3.0);
+            triangles.push(Triangle {
+                verts: [triangle[0], triangle[1], triangle[2]],
+                texture_id: texture_id as u8,
+                node: *node,
+                skinned: *skinned,
+                morphs: [morphs[0].clone(), morphs[1].clone(), morphs[2].clone()],
+            });
+        }
+
+        // Create PSX texture collection for this submesh
+        {
+            // The texture dimensions have to fit in a single byte
+            if mat.texture.width > u8::MAX as usize || mat.texture.height > u8::MAX as usize {
+                return Err(ConvertError::overflow(
+                    &context,
+                    format!(
+                        "the texture is {}x{}, which is larger than 255x255",
+                        mat.texture.width, mat.texture.height
+                    ),
+                ));
+            }
+
+            // For debug purposes, export the textures
+            if options.debug_view {
+                let mut pixels = Vec::new();
+                for value in &mat.texture.data {
+                    pixels.push(((value >> 0) & 0xFF) as u8);
+                    pixels.push(((value >> 8) & 0xFF) as u8);
+                    pixels.push(((value >> 16) & 0xFF) as u8);
+                    pixels.push(((value >> 24) & 0xFF) as u8);
+                }
+                let image_data = RgbaImage::from_vec(
+                    mat.texture.width as u32,
+                    mat.texture.height as u32,
+                    pixels,
+                )
+                .ok_or_else(|| ConvertError::texture(&context, "invalid texture dimensions"))?;
+                let output = DynamicImage::ImageRgba8(image_data);
+                output
+                    .save(format!("{}.png", mat.name))
+                    .map_err(|err| ConvertError::texture(&context, err.to_string()))?;
+            }
+
+            // Create texture cell object
+            let mut tex_cell = TextureCellPSX {
+                texture_data: Vec::new(),
+                palette: Vec::new(),
+                texture_width: mat.texture.width as u8,
+                texture_height: mat.texture.height as u8,
+                avg_color: 0,
+            };
+
+            // Quantize it to 16 colours
+            let mut tex_data_exoquant = Vec::new();
+            let tex_data_src = &mat.texture.data;
+            for pixel in tex_data_src {
+                let pixel8 = pixel.to_be_bytes();
+                tex_data_exoquant.push(exoquant::Color::new(
+                    pixel8[3], pixel8[2], pixel8[1], pixel8[0],
+                ));
+            }
+            let (mut palette, indexed_data) = match options.dither {
+                Dither::None => convert_to_indexed(
+                    &tex_data_exoquant,
+                    mat.texture.width,
+                    options.palette_size,
+                    &optimizer::KMeans,
+                    &ditherer::None,
+                ),
+                Dither::Ordered => convert_to_indexed(
+                    &tex_data_exoquant,
+                    mat.texture.width,
+                    options.palette_size,
+                    &optimizer::KMeans,
+                    &ditherer::Ordered,
+                ),
+                Dither::FloydSteinberg => convert_to_indexed(
+                    &tex_data_exoquant,
+                    mat.texture.width,
+                    options.palette_size,
+                    &optimizer::KMeans,
+                    &ditherer::FloydSteinberg::new(),
+                ),
+            };
+
+            // Every palette takes up 16 colors in the file, even if not all of them are used
+            palette.resize(16, Color::new(0, 0, 0, 0));
+            let color_b = Color {
+                r: (mat.texture.avg_color & 0x000000FF >> 0) as u8,
+                g: (mat.texture.avg_color & 0x0000FF00 >> 8) as u8,
+                b: (mat.texture.avg_color & 0x00FF0000 >> 16) as u8,
+                a: (mat.texture.avg_color & 0xFF000000 >> 24) as u8,
+            };
+            for fade_level in 0..16 {
+                for color in &palette {
+                    let color: u16 = (color.a as u16).clamp(0, 1) << 15
+                        | ((((fade_level * color_b.b as u16)
+                            + ((15 - fade_level) * color.b as u16))
+                            / 15)
+                            >> 3)
+                            .clamp(0, 31)
+                            << 10
+                        | ((((fade_level * color_b.g as u16)
+                            + ((15 - fade_level) * color.g as u16))
+                            / 15)
+                            >> 3)
+                            .clamp(0, 31)
+                            << 5
+                        | ((((fade_level * color_b.r as u16)
+                            + ((15 - fade_level) * color.r as u16))
+                            / 15)
+                            >> 3)
+                            .clamp(0, 31)
+                            << 0;
+                    tex_cell.palette.push(color);
+                }
+            }
+
+            // Convert indices to 4 bit
+            for i in (0..(mat.texture.width * mat.texture.height)).step_by(2) {
+                if (i + 1) < indexed_data.len() {
+                    tex_cell
+                        .texture_data
+                        .push((indexed_data[i + 0] << 4) | (indexed_data[i + 1]));
+                } else {
+                    tex_cell.texture_data.push(0);
+                    tex_cell.texture_data.push(0);
+                    tex_cell.texture_data.push(0);
+                    tex_cell.texture_data.push(0);
+                }
+            }
+
+            // Set average color in cell
+            tex_cell.avg_color = mat.texture.avg_color;
+
+            // Add this cell to the collection
+            txc_psx_out.texture_cells.push(tex_cell);
+            txc_psx_out.texture_names.push(mat.name.clone());
+        }
+    }
+
+    // Split the triangles into submeshes, and put them in the model_psx
+    let mut warnings = model.warnings;
+    let groups = match options.instancing {
+        true => group_instanced_triangles(&triangles)
+            .into_iter()
+            .map(|(owner, group)| (Some(owner), group))
+            .collect(),
+        false => group_triangles(
+            &triangles,
+            &centroids,
+            &model.node_names,
+            &model.node_parents,
+            &model.bones,
+            &model.morphs,
+            &model.instances,
+            options,
+        ),
+    };
+    // Remember which node or bone each submesh was made for, so the instances, bones and morph targets can refer to it
+    let mut owner_submeshes = BTreeMap::<SubmeshOwner, usize>::new();
+    for (submesh_index, (owner, group)) in groups.iter().enumerate() {
+        let mut mesh = MeshPSX::new();
+        owner_submeshes.extend(owner.map(|owner| (owner, submesh_index)));
+        if !options.instancing {
+            mesh.name = owner.map(|owner| model.node_names[owner.node()].clone());
+        }
+
+        // Find the fixed-point positions of every triangle in this submesh
+        let positions: Vec<[Vec3; 3]> = group
+            .iter()
+            .map(|index| {
+                triangles[*index]
+                    .verts
+                    .map(|vertex| position_matrix * vertex.position)
+            })
+            .collect();
+
+        // Store the positions relative to the center of the submesh if requested, so big levels keep their precision
+        if options.relative_origins {
+            let mut min = Vec3::splat(f32::MAX);
+            let mut max = Vec3::splat(f32::MIN);
+            for position in positions.iter().flatten() {
+                min = min.min(*position);
+                max = max.max(*position);
+            }
+            let center = ((min + max) / 2.0).round();
+            mesh.origin = [center.x as i32, center.y as i32, center.z as i32];
+        }
+        let origin = Vec3::new(
+            mesh.origin[0] as f32,
+            mesh.origin[1] as f32,
+            mesh.origin[2] as f32,
+        );
+
+        // Convert each triangle to a PSX triangle, and keep track of the ones that don't fit in 16 bits
+        let mut n_overflowing_triangles = 0;
+        let mut overflowing_nodes = Vec::<usize>::new();
+        for (index, positions) in group.iter().zip(positions) {
+            let triangle = &triangles[*index];
+            let positions = positions.map(|position| position - origin);
+            if positions.iter().any(|position| {
+                position.min_element() < -32768.0 || position.max_element() > 32767.0
+            }) {
+                n_overflowing_triangles += 1;
+                if !overflowing_nodes.contains(&triangle.node) {
+                    overflowing_nodes.push(triangle.node);
+                }
+            }
+
+            let mut triangle_psx = [0, 1, 2]
+                .map(|i| VertexPSX::from(&triangle.verts[i], positions[i], triangle.texture_id));
+
+            // If the axis mapping mirrors the model, the triangles have to be flipped to keep facing the same way
+            if flip_winding {
+                triangle_psx.swap(1, 2);
+            }
+            mesh.verts.extend_from_slice(&triangle_psx);
+        }
+
+        // Report which parts of the model got squashed
+        if n_overflowing_triangles > 0 {
+            let node_names: Vec<String> = overflowing_nodes
+                .iter()
+                .map(|node| format!("'{}'", model.node_names[*node]))
+                .collect();
+            warnings.push(format!(
+                "submesh {submesh_index}: {n_overflowing_triangles} triangle(s) from node(s) {} are outside of the 16-bit range and were clamped",
+                node_names.join(", ")
+            ));
+        }
+
+        model_psx_out.meshes.push(mesh);
+    }
+
+    // The game looks submeshes up by the hash of their name, so it can't tell apart the ones that share a hash
+    let mut named_submeshes = BTreeMap::<u32, usize>::new();
+    for (submesh_index, mesh) in model_psx_out.meshes.iter().enumerate() {
+        let Some(name) = &mesh.name else {
+            continue;
+        };
+        match named_submeshes.get(&name_hash(name)) {
+            Some(first) => warnings.push(format!(
+                "submesh {submesh_index}: the hash of its name '{name}' is the same as the one of submesh {first}, so it can't be looked up by name"
+            )),
+            None => {
+                named_submeshes.insert(name_hash(name), submesh_index);
+            }
+        }
+    }
+
+    // Place the instances, skipping the ones whose mesh ended up without any triangles
+    for instance in &model.instances {
+        match owner_submeshes.get(&SubmeshOwner::Node(instance.prototype)) {
+            Some(submesh_index) => {
+                let context = format!("node '{}'", model.node_names[instance.node]);
+                model_psx_out.instances.push(InstancePSX {
+                    submesh_index: *submesh_index as u32,
+                    transform: transform_to_psx(
+                        instance.transform,
+                        position_matrix,
+                        &options.axes,
+                        &context,
+                        &mut warnings,
+                    ),
+                });
+            }
+            None => warnings.push(format!(
+                "node '{}': its mesh has no triangles, so the instance was skipped",
+                model.node_names[instance.node]
+            )),
+        }
+    }
+
+    // Write the skeleton, with the bind pose of each bone relative to its parent
+    let mut skeleton_psx_out = SkeletonPSX::new();
+    for bone in &model.bones {
+        let name = &model.node_names[bone.node];
+        let local_bind = match bone.parent {
+            Some(parent) => model.bones[parent].bind.inverse() * bone.bind,
+            None => bone.bind,
+        };
+        skeleton_psx_out.bones.push(BonePSX {
+            name: name.clone(),
+            parent: bone.parent.map(|parent| parent as u32),
+            submesh_index: owner_submeshes
+                .get(&SubmeshOwner::Bone(bone.node))
+                .map(|index| *index as u32),
+            transform: transform_to_psx(
+                local_bind,
+                position_matrix,
+                &options.axes,
+                &format!("bone '{name}'"),
+                &mut warnings,
+            ),
+        });
+    }
+
+    // Resample every animation clip, and store the transform of each animated node and bone for every frame
+    let mut animations_psx_out = AnimationCollectionPSX::new();
+    animations_psx_out.frame_rate = options.frame_rate;
+    for animation in &model.animations {
+        let n_frames = animation.frame_count(options.frame_rate);
+        let duration = animation.duration();
+        let mut clip = ClipPSX {
+            name: animation.name.clone(),
+            n_frames: n_frames as u32,
+            tracks: Vec::new(),
+        };
+        // Only the bones, entities and meshes in local space can be moved by the game
+        let animated_nodes = animation.nodes();
+        let mut tracked_nodes: Vec<usize> = model.bones.iter().map(|bone| bone.node).collect();
+        tracked_nodes.extend(model.entities.iter().map(|entity| entity.node));
+        tracked_nodes.extend(model.instances.iter().map(|instance| instance.node));
+        tracked_nodes.sort();
+        tracked_nodes.dedup();
+        for node in tracked_nodes {
+            // A bone moves relative to its parent bone, or to model space for a root bone. Any other node
+            // moves in model space, like its instance or entity. Either way the frames combine every node in between
+            let bone = model.bones.iter().position(|bone| bone.node == node);
+            let parent_node = bone
+                .and_then(|bone| model.bones[bone].parent)
+                .map(|parent| model.bones[parent].node);
+            let chain = node_chain(&model.node_parents, node, parent_node);
+            if !chain.iter().any(|node| animated_nodes.contains(node)) {
+                continue;
+            }
+            let context = format!(
+                "animation '{}', node '{}'",
+                animation.name, model.node_names[node]
+            );
+            let mut frames = Vec::with_capacity(n_frames);
+            for frame in 0..n_frames {
+                let time = (frame as f32 / options.frame_rate).min(duration);
+                let transform = chain.iter().fold(Mat4::IDENTITY, |transform, node| {
+                    animation.sample_node(*node, &model.node_transforms[*node], time) * transform
+                });
+                frames.push(transform_to_psx(
+                    transform,
+                    position_matrix,
+                    &options.axes,
+                    &context,
+                    &mut warnings,
+                ));
+            }
+            clip.tracks.push(TrackPSX {
+                name: model.node_names[node].clone(),
+                bone_index: bone.map(|index| index as u32),
+                submesh_index: match bone {
+                    Some(_) => owner_submeshes.get(&SubmeshOwner::Bone(node)),
+                    None => model
+                        .instances
+                        .iter()
+                        .find(|instance| instance.node == node)
+                        .and_then(|instance| {
+                            owner_submeshes.get(&SubmeshOwner::Node(instance.prototype))
+                        }),
+                }
+                .map(|index| *index as u32),
+                frames,
+            });
+        }
+
+        // Clips that don't move anything the game can place are left out, like the ones that only animate
+        // morph target weights, which go in the vertex animations instead
+        if !clip.tracks.is_empty() {
+            animations_psx_out.clips.push(clip);
+        }
+    }
+
+    // Store the offsets of every morph target, and bake each clip that animates the weights into offsets per frame
+    let mut vertex_animations_psx_out = VertexAnimationPSX::new();
+    vertex_animations_psx_out.frame_rate = options.frame_rate;
+    for morph in &model.morphs {
+        let Some(submesh_index) = owner_submeshes.get(&SubmeshOwner::Node(morph.node)) else {
+            continue;
+        };
+        let context = format!("node '{}'", model.node_names[morph.node]);
+
+        // The offsets of every vertex in the submesh, in the same order as the vertices were written.
+        // Vertices of other nodes in the same submesh don't move
+        let n_targets = morph.target_names.len();
+        let mut vertex_morphs = Vec::<Vec<Vec3>>::new();
+        for index in &groups[*submesh_index].1 {
+            let triangle = &triangles[*index];
+            let mut order = [0, 1, 2];
+            if flip_winding {
+                order.swap(1, 2);
+            }
+            for i in order {
+                vertex_morphs.push(match triangle.node == morph.node {
+                    true => triangle.morphs[i].clone(),
+                    false => Vec::new(),
+                });
+            }
+        }
+        let mut frame_to_psx = |weights: &[f32]| -> Vec<[i16; 3]> {
+            let mut overflow = false;
+            let frame = vertex_morphs
+                .iter()
+                .map(|offsets| {
+                    let offset: Vec3 = offsets.iter().zip(weights).map(|(o, w)| *o * *w).sum();
+                    let offset = (position_matrix * offset).round();
+                    overflow |= offset.abs().max_element() > i16::MAX as f32;
+                    offset.to_array().map(|x| x.clamp(-32768.0, 32767.0) as i16)
+                })
+                .collect();
+            if overflow {
+                warnings.push(format!(
+                    "{context}: a morph target offset doesn't fit in 16 bits and was clamped"
+                ));
+            }
+            frame
+        };
+
+        // Each morph target on its own, at full weight
+        for (target, name) in morph.target_names.iter().enumerate() {
+            let mut weights = vec![0.0; n_targets];
+            weights[target] = 1.0;
+            vertex_animations_psx_out.sequences.push(MorphSequencePSX {
+                name: name.clone(),
+                submesh_index: *submesh_index as u32,
+                animated: false,
+                frames: vec![frame_to_psx(&weights)],
+            });
+        }
+
+        // Then every clip that animates the weights of this node, resampled like the node animations
+        for animation in &model.animations {
+            if !animation.animates_weights(morph.node) {
+                continue;
+            }
+            let n_frames = animation.frame_count(options.frame_rate);
+            let duration = animation.duration();
+            let frames = (0..n_frames)
+                .map(|frame| {
+                    let time = (frame as f32 / options.frame_rate).min(duration);
+                    frame_to_psx(&animation.sample_weights(morph.node, &morph.weights, time))
+                })
+                .collect();
+            vertex_animations_psx_out.sequences.push(MorphSequencePSX {
+                name: animation.name.clone(),
+                submesh_index: *submesh_index as u32,
+                animated: true,
+                frames,
+            });
+        }
+    }
+
+    // A track that doesn't fit would otherwise warn for every single frame, and several tracks can interleave
+    let mut seen = BTreeSet::new();
+    warnings.retain(|warning| seen.insert(warning.clone()));
+
+    // Nodes without a mesh become entities
+    let mut entities_psx_out = EntityCollectionPSX::new();
+    for entity in &model.entities {
+        let name = &model.node_names[entity.node];
+        let context = format!("node '{name}'");
+        entities_psx_out.entities.push(EntityPSX {
+            name: name.clone(),
+            transform: transform_to_psx(
+                entity.transform,
+                position_matrix,
+                &options.axes,
+                &context,
+                &mut warnings,
+            ),
+            properties: entity_properties(
+                &entity.extras,
+                &options.entity_properties,
+                &context,
+                &mut warnings,
+            ),
+        });
+    }
+
+    Ok(ConvertOutput {
+        model: model_psx_out,
+        textures: txc_psx_out,
+        entities: entities_psx_out,
+        skeleton: skeleton_psx_out,
+        animations: animations_psx_out,
+        vertex_animations: vertex_animations_psx_out,
+        warnings,
+    })
+}
@@ -0,0 +1,159 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Everything that can go wrong while converting or saving a model.
+///
+/// Every variant carries the path of the asset it came from, and where it makes sense a
+/// human readable context like `node 'door_01', mesh 'Cube.003'` so a broken asset can be
+/// found in a large batch.
+#[derive(Debug)]
+pub enum ConvertError {
+    /// A file could not be read or written.
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The glTF file could not be parsed, or refers to data that doesn't exist.
+    MalformedGltf {
+        path: PathBuf,
+        context: String,
+        message: String,
+    },
+    /// The glTF file uses a feature that this converter does not handle.
+    Unsupported {
+        path: PathBuf,
+        context: String,
+        feature: String,
+    },
+    /// A value does not fit in the fields of the PSX file formats.
+    Overflow {
+        path: PathBuf,
+        context: String,
+        message: String,
+    },
+    /// A texture could not be decoded, converted or written.
+    Texture {
+        path: PathBuf,
+        context: String,
+        message: String,
+    },
+}
+
+impl ConvertError {
+    pub fn io(path: &Path, source: std::io::Error) -> Self {
+        ConvertError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub fn malformed(context: impl Into<String>, message: impl Into<String>) -> Self {
+        ConvertError::MalformedGltf {
+            path: PathBuf::new(),
+            context: context.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn unsupported(context: impl Into<String>, feature: impl Into<String>) -> Self {
+        ConvertError::Unsupported {
+            path: PathBuf::new(),
+            context: context.into(),
+            feature: feature.into(),
+        }
+    }
+
+    pub fn overflow(context: impl Into<String>, message: impl Into<String>) -> Self {
+        ConvertError::Overflow {
+            path: PathBuf::new(),
+            context: context.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn texture(context: impl Into<String>, message: impl Into<String>) -> Self {
+        ConvertError::Texture {
+            path: PathBuf::new(),
+            context: context.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Convert an error returned by the gltf crate while importing `path`.
+    pub fn from_gltf(path: &Path, error: gltf::Error) -> Self {
+        match error {
+            gltf::Error::Io(source) => ConvertError::io(path, source),
+            error => ConvertError::MalformedGltf {
+                path: path.to_path_buf(),
+                context: String::new(),
+                message: error.to_string(),
+            },
+        }
+    }
+
+    /// The asset path this error belongs to. Empty if the asset was loaded from memory.
+    pub fn path(&self) -> &Path {
+        match self {
+            ConvertError::Io { path, .. }
+            | ConvertError::MalformedGltf { path, .. }
+            | ConvertError::Unsupported { path, .. }
+            | ConvertError::Overflow { path, .. }
+            | ConvertError::Texture { path, .. } => path,
+        }
+    }
+
+    /// Fill in the asset path, if it wasn't known yet where the error was created.
+    pub fn with_path(mut self, asset_path: &Path) -> Self {
+        match &mut self {
+            ConvertError::Io { path, .. }
+            | ConvertError::MalformedGltf { path, .. }
+            | ConvertError::Unsupported { path, .. }
+            | ConvertError::Overflow { path, .. }
+            | ConvertError::Texture { path, .. } => {
+                if path.as_os_str().is_empty() {
+                    *path = asset_path.to_path_buf();
+                }
+            }
+        }
+        self
+    }
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Start with the asset path and the context, if we have them
+        let (path, context) = match self {
+            ConvertError::Io { path, .. } => (path, ""),
+            ConvertError::MalformedGltf { path, context, .. }
+            | ConvertError::Unsupported { path, context, .. }
+            | ConvertError::Overflow { path, context, .. }
+            | ConvertError::Texture { path, context, .. } => (path, context.as_str()),
+        };
+        if !path.as_os_str().is_empty() {
+            write!(f, "{}: ", path.display())?;
+        }
+        if !context.is_empty() {
+            write!(f, "{context}: ")?;
+        }
+
+        // Then the actual error
+        match self {
+            ConvertError::Io { source, .. } => write!(f, "i/o error: {source}"),
+            ConvertError::MalformedGltf { message, .. } => write!(f, "malformed glTF: {message}"),
+            ConvertError::Unsupported { feature, .. } => write!(f, "unsupported: {feature}"),
+            ConvertError::Overflow { message, .. } => write!(f, "overflow: {message}"),
+            ConvertError::Texture { message, .. } => write!(f, "texture error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConvertError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
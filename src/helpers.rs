@@ -1,36 +1,31 @@
-use glam::Vec2;
-
-pub fn index_to_coords(index: usize, width: usize) -> glam::Vec2 {
-    glam::vec2((index % width) as f32, (index / width) as f32)
-}
 pub fn coords_to_index(x: usize, y: usize, width: usize) -> usize {
     x + (y * width)
 }
-pub fn colour_rgb(red: u8, green: u8, blue: u8) -> u32 {
-    ((red as u32) << 16) + ((green as u32) << 8) + (blue as u32)
-}
-pub fn colour_rgba(alpha: u8, red: u8, green: u8, blue: u8) -> u32 {
-    ((alpha as u32) << 24) + ((red as u32) << 16) + ((green as u32) << 8) + (blue as u32)
-}
-
-pub fn to_argb8(a: u8, r: u8, g: u8, b: u8) -> u32 {
-    (a as u32) << 24 | (r as u32) << 16 | (g as u32) << 8 | (b as u32)
-}
 
-pub fn edge_function(v0: Vec2, v1: Vec2, p: Vec2) -> f32 {
-    let v0_p = p - v0;
-    let v0_v1 = v1 - v0;
-    (v0_p.x * v0_v1.y) - (v0_p.y * v0_v1.x)
-}
+/// Check if a name matches a pattern, where `*` matches any number of characters and `?` matches a single one.
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
 
-fn point_inside_triangle(v0: Vec2, v1: Vec2, v2: Vec2, p: Vec2) -> bool {
-    (edge_function(v0, v1, p) > 0.0)
-        && (edge_function(v1, v2, p) > 0.0)
-        && (edge_function(v2, v0, p) > 0.0)
-}
-
-pub fn validate(r: std::io::Result<usize>) {
-    if r.is_err() {
-        panic!();
+    // Walk through both, and when a mismatch happens after a `*`, let that `*` eat one more character
+    let (mut p, mut n) = (0, 0);
+    let mut last_star = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            last_star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = last_star {
+            p = star_p + 1;
+            n = star_n + 1;
+            last_star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
     }
+
+    // Only stars can be left in the pattern
+    pattern[p..].iter().all(|c| *c == '*')
 }
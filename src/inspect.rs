@@ -0,0 +1,860 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{
+    error::ConvertError,
+    psx_structs::{
+        i16_at, name_hash, u32_at, BoneBinary, ClipBinary, EntityBinary, InstancePSX, MeshDesc,
+        PropertyBinary, SequenceBinary, TextureCellBinary, TrackBinary, TransformPSX,
+        ANM_HEADER_SIZE, ANM_VERSION, ENT_HEADER_SIZE, ENT_VERSION, MSH_HEADER_SIZE, MSH_VERSION,
+        NO_INDEX, NO_TABLE, PROPERTY_BOOL, PROPERTY_FIXED, PROPERTY_INT, PROPERTY_STRING,
+        SEQUENCE_ANIMATED, SKL_HEADER_SIZE, SKL_VERSION, VTA_HEADER_SIZE, VTA_VERSION,
+    },
+};
+
+fn read_u32<R: Read>(file: &mut R) -> Result<u32, ConvertError> {
+    let mut buf32 = [0u8; 4];
+    file.read_exact(&mut buf32)
+        .map_err(|err| ConvertError::io(Path::new(""), err))?;
+    Ok(u32_at(&buf32, 0))
+}
+
+fn read_at<R: Read + Seek>(
+    file: &mut R,
+    offset: u64,
+    buffer: &mut [u8],
+) -> Result<(), ConvertError> {
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_exact(buffer))
+        .map_err(|err| ConvertError::io(Path::new(""), err))
+}
+
+fn file_length<R: Seek>(file: &mut R) -> Result<u64, ConvertError> {
+    file.seek(SeekFrom::End(0))
+        .map_err(|err| ConvertError::io(Path::new(""), err))
+}
+
+/// Read a null-terminated string at `offset` into the binary section.
+/// Returns `None` if the string doesn't end before `number_of_bytes`.
+fn read_string<R: Read + Seek>(
+    file: &mut R,
+    binary_offset: u64,
+    offset: u64,
+    number_of_bytes: u64,
+) -> Result<Option<String>, ConvertError> {
+    let mut bytes = Vec::new();
+    let mut offset = offset;
+    loop {
+        if offset >= number_of_bytes {
+            return Ok(None);
+        }
+        let mut byte = [0u8];
+        read_at(file, binary_offset + offset, &mut byte)?;
+        if byte[0] == 0 {
+            return Ok(Some(String::from_utf8_lossy(&bytes).into_owned()));
+        }
+        bytes.push(byte[0]);
+        offset += 1;
+    }
+}
+
+/// Print the contents of a .msh file and check that all offsets stay inside the file.
+/// Returns `Ok(false)` if the file is not a valid .msh file.
+pub fn debug_msh<R: Read + Seek>(file: &mut R) -> Result<bool, ConvertError> {
+    println!("FMSH file debug");
+
+    // Verify file magic
+    let mut file_magic = [0u8; 4];
+    read_at(file, 0, &mut file_magic)?;
+    match &file_magic == b"FMSH" {
+        true => println!("File magic ok. (\"FMSH\")"),
+        false => {
+            println!("File magic not ok. Invalid file.");
+            return Ok(false);
+        }
+    }
+
+    // Verify format version
+    let version = read_u32(file)?;
+    println!("version: {version}");
+    if version != MSH_VERSION {
+        println!("Unsupported version, expected {MSH_VERSION}. Invalid file.");
+        return Ok(false);
+    }
+
+    // Verify number of submeshes
+    let n_submeshes = read_u32(file)?;
+    println!("n_submeshes: {n_submeshes}");
+
+    // Get mesh description offset
+    let offset_mesh_desc = read_u32(file)?;
+    println!("offset_mesh_desc: {offset_mesh_desc}");
+
+    // Get vertex data offset
+    let offset_vertex_data = read_u32(file)?;
+    println!("offset_vertex_data: {offset_vertex_data}");
+
+    // Get the scale
+    let fixed_point_scale = read_u32(file)?;
+    println!(
+        "fixed_point_scale: {} ({} units per glTF unit)",
+        fixed_point_scale,
+        fixed_point_scale as f64 / 65536.0
+    );
+
+    // Get name and instance table offsets
+    let offset_name_table = read_u32(file)?;
+    println!("offset_name_table: {offset_name_table}");
+    let offset_instance_table = read_u32(file)?;
+    println!("offset_instance_table: {offset_instance_table}");
+
+    // The binary data starts right after the header
+    let binary_offset = MSH_HEADER_SIZE as u64;
+
+    // Check if the offsets are sane
+    let number_of_bytes = file_length(file)?.saturating_sub(binary_offset);
+    if offset_mesh_desc as u64 + n_submeshes as u64 * MeshDesc::SIZE as u64 > number_of_bytes
+        || offset_vertex_data as u64 > number_of_bytes
+    {
+        println!("Offsets are out of bounds! File is unsafe!");
+        return Ok(false);
+    }
+
+    // First read all the mesh descriptions
+    let mut buf_mesh_desc = [0u8; MeshDesc::SIZE];
+    let mut highest_vertex_index = 0u64;
+
+    for submesh_index in 0..n_submeshes {
+        read_at(
+            file,
+            binary_offset
+                + offset_mesh_desc as u64
+                + (submesh_index as u64 * MeshDesc::SIZE as u64),
+            &mut buf_mesh_desc,
+        )?;
+        let mesh_desc = MeshDesc::from_bytes(&buf_mesh_desc);
+        println!("mesh_descs[{submesh_index}]:");
+        println!("\tvertex_start: {}", mesh_desc.vertex_start);
+        println!("\tn_vertices: {}", mesh_desc.n_vertices);
+        println!("\tx_min, x_max: {}, {}", mesh_desc.x_min, mesh_desc.x_max);
+        println!("\ty_min, y_max: {}, {}", mesh_desc.y_min, mesh_desc.y_max);
+        println!("\tz_min, z_max: {}, {}", mesh_desc.z_min, mesh_desc.z_max);
+        println!(
+            "\torigin: {}, {}, {}",
+            mesh_desc.origin_x, mesh_desc.origin_y, mesh_desc.origin_z
+        );
+        highest_vertex_index =
+            highest_vertex_index.max(mesh_desc.vertex_start as u64 + mesh_desc.n_vertices as u64);
+    }
+
+    // Check if vertex indices fit inside the binary section
+    if offset_vertex_data as u64 + highest_vertex_index * 12 > number_of_bytes {
+        println!("Vertex data is out of bounds! File is unsafe!");
+        return Ok(false);
+    }
+
+    // Print the names of the named submeshes
+    if offset_name_table != NO_TABLE {
+        if offset_name_table as u64 + 4 > number_of_bytes {
+            println!("Name table is out of bounds! File is unsafe!");
+            return Ok(false);
+        }
+        let mut buf32 = [0u8; 4];
+        read_at(file, binary_offset + offset_name_table as u64, &mut buf32)?;
+        let n_names = u32_at(&buf32, 0);
+        println!("n_names: {n_names}");
+        if offset_name_table as u64 + 4 + n_names as u64 * 12 > number_of_bytes {
+            println!("Name table is out of bounds! File is unsafe!");
+            return Ok(false);
+        }
+        for name_index in 0..n_names as u64 {
+            let mut entry = [0u8; 12];
+            read_at(
+                file,
+                binary_offset + offset_name_table as u64 + 4 + name_index * 12,
+                &mut entry,
+            )?;
+            let hash = u32_at(&entry, 0);
+            let submesh_index = u32_at(&entry, 4);
+            let offset_name = u32_at(&entry, 8);
+            let Some(name) = read_string(file, binary_offset, offset_name as u64, number_of_bytes)?
+            else {
+                println!("Name {name_index} is out of bounds! File is unsafe!");
+                return Ok(false);
+            };
+            println!("names[{name_index}]: '{name}' (hash {hash:08X}) -> submesh {submesh_index}");
+            if submesh_index >= n_submeshes || hash != name_hash(&name) {
+                println!("Name {name_index} does not match its submesh or hash. Invalid file.");
+                return Ok(false);
+            }
+        }
+    }
+
+    // Print the instances
+    if offset_instance_table != NO_TABLE {
+        if offset_instance_table as u64 + 4 > number_of_bytes {
+            println!("Instance table is out of bounds! File is unsafe!");
+            return Ok(false);
+        }
+        let mut buf32 = [0u8; 4];
+        read_at(
+            file,
+            binary_offset + offset_instance_table as u64,
+            &mut buf32,
+        )?;
+        let n_instances = u32_at(&buf32, 0);
+        println!("n_instances: {n_instances}");
+        if offset_instance_table as u64 + 4 + n_instances as u64 * InstancePSX::SIZE as u64
+            > number_of_bytes
+        {
+            println!("Instance table is out of bounds! File is unsafe!");
+            return Ok(false);
+        }
+        let mut buf_instance = [0u8; InstancePSX::SIZE];
+        for instance_index in 0..n_instances as u64 {
+            read_at(
+                file,
+                binary_offset
+                    + offset_instance_table as u64
+                    + 4
+                    + instance_index * InstancePSX::SIZE as u64,
+                &mut buf_instance,
+            )?;
+            let instance = InstancePSX::from_bytes(&buf_instance);
+            println!("instances[{instance_index}]:");
+            println!("\tsubmesh_index: {}", instance.submesh_index);
+            println!("\tposition: {:?}", instance.transform.position);
+            println!("\trotation: {:?}", instance.transform.rotation);
+            println!("\tscale: {:?}", instance.transform.scale);
+            if instance.submesh_index >= n_submeshes {
+                println!(
+                    "Instance {instance_index} uses a submesh that doesn't exist. Invalid file."
+                );
+                return Ok(false);
+            }
+        }
+    }
+
+    println!("File is ok.");
+
+    Ok(true)
+}
+
+/// Print the contents of a .ent file and check that all offsets stay inside the file.
+/// Returns `Ok(false)` if the file is not a valid .ent file.
+pub fn debug_ent<R: Read + Seek>(file: &mut R) -> Result<bool, ConvertError> {
+    println!("FENT file debug");
+
+    // Verify file magic
+    let mut file_magic = [0u8; 4];
+    read_at(file, 0, &mut file_magic)?;
+    match &file_magic == b"FENT" {
+        true => println!("File magic ok. (\"FENT\")"),
+        false => {
+            println!("File magic not ok. Invalid file.");
+            return Ok(false);
+        }
+    }
+
+    // Verify format version
+    let version = read_u32(file)?;
+    println!("version: {version}");
+    if version != ENT_VERSION {
+        println!("Unsupported version, expected {ENT_VERSION}. Invalid file.");
+        return Ok(false);
+    }
+
+    let n_entities = read_u32(file)?;
+    let n_properties = read_u32(file)?;
+    let offset_entities = read_u32(file)?;
+    let offset_properties = read_u32(file)?;
+    let offset_strings = read_u32(file)?;
+    println!("n_entities: {n_entities}");
+    println!("n_properties: {n_properties}");
+    println!("offset_entities: {offset_entities}");
+    println!("offset_properties: {offset_properties}");
+    println!("offset_strings: {offset_strings}");
+
+    // The binary data starts right after the header
+    let binary_offset = ENT_HEADER_SIZE as u64;
+
+    // Check if the offsets are sane
+    let number_of_bytes = file_length(file)?.saturating_sub(binary_offset);
+    if offset_entities as u64 + n_entities as u64 * EntityBinary::SIZE as u64 > number_of_bytes
+        || offset_properties as u64 + n_properties as u64 * PropertyBinary::SIZE as u64
+            > number_of_bytes
+        || offset_strings as u64 > number_of_bytes
+    {
+        println!("Offsets are out of bounds! File is unsafe!");
+        return Ok(false);
+    }
+
+    // Print every entity, together with its properties
+    let mut buf_entity = [0u8; EntityBinary::SIZE];
+    let mut buf_property = [0u8; PropertyBinary::SIZE];
+    for entity_index in 0..n_entities as u64 {
+        read_at(
+            file,
+            binary_offset + offset_entities as u64 + entity_index * EntityBinary::SIZE as u64,
+            &mut buf_entity,
+        )?;
+        let entity = EntityBinary::from_bytes(&buf_entity);
+        let Some(name) = read_string(
+            file,
+            binary_offset,
+            entity.offset_name as u64,
+            number_of_bytes,
+        )?
+        else {
+            println!("Name of entity {entity_index} is out of bounds! File is unsafe!");
+            return Ok(false);
+        };
+        println!("entities[{entity_index}]:");
+        println!("\tname: {name} (hash {:08X})", entity.name_hash);
+        println!("\tposition: {:?}", entity.transform.position);
+        println!("\trotation: {:?}", entity.transform.rotation);
+        println!("\tscale: {:?}", entity.transform.scale);
+        if entity.name_hash != name_hash(&name) {
+            println!("Hash of entity {entity_index} doesn't match its name. Invalid file.");
+            return Ok(false);
+        }
+        if entity.first_property as u64 + entity.n_properties as u64 > n_properties as u64 {
+            println!("Properties of entity {entity_index} are out of bounds! File is unsafe!");
+            return Ok(false);
+        }
+
+        for property_index in entity.first_property..entity.first_property + entity.n_properties {
+            read_at(
+                file,
+                binary_offset
+                    + offset_properties as u64
+                    + property_index as u64 * PropertyBinary::SIZE as u64,
+                &mut buf_property,
+            )?;
+            let property = PropertyBinary::from_bytes(&buf_property);
+            let Some(key) = read_string(
+                file,
+                binary_offset,
+                property.offset_key as u64,
+                number_of_bytes,
+            )?
+            else {
+                println!("Key of property {property_index} is out of bounds! File is unsafe!");
+                return Ok(false);
+            };
+            if property.key_hash != name_hash(&key) {
+                println!("Hash of property {property_index} doesn't match its key. Invalid file.");
+                return Ok(false);
+            }
+            let value = match property.kind {
+                PROPERTY_BOOL => format!("{}", property.value != 0),
+                PROPERTY_INT => format!("{}", property.value as i32),
+                PROPERTY_FIXED => format!("{}", property.value as i32 as f64 / 4096.0),
+                PROPERTY_STRING => {
+                    match read_string(file, binary_offset, property.value as u64, number_of_bytes)?
+                    {
+                        Some(value) => format!("{value:?}"),
+                        None => {
+                            println!("Value of property {property_index} is out of bounds! File is unsafe!");
+                            return Ok(false);
+                        }
+                    }
+                }
+                kind => {
+                    println!("Property {property_index} has unknown type {kind}. Invalid file.");
+                    return Ok(false);
+                }
+            };
+            println!("\t{key}: {value}");
+        }
+    }
+
+    println!("File is ok.");
+
+    Ok(true)
+}
+
+/// Print the contents of a .skl file and check that all offsets and bone indices stay inside the file.
+/// Returns `Ok(false)` if the file is not a valid .skl file.
+pub fn debug_skl<R: Read + Seek>(file: &mut R) -> Result<bool, ConvertError> {
+    println!("FSKL file debug");
+
+    // Verify file magic
+    let mut file_magic = [0u8; 4];
+    read_at(file, 0, &mut file_magic)?;
+    match &file_magic == b"FSKL" {
+        true => println!("File magic ok. (\"FSKL\")"),
+        false => {
+            println!("File magic not ok. Invalid file.");
+            return Ok(false);
+        }
+    }
+
+    // Verify format version
+    let version = read_u32(file)?;
+    println!("version: {version}");
+    if version != SKL_VERSION {
+        println!("Unsupported version, expected {SKL_VERSION}. Invalid file.");
+        return Ok(false);
+    }
+
+    let n_bones = read_u32(file)?;
+    let offset_bones = read_u32(file)?;
+    let offset_names = read_u32(file)?;
+    println!("n_bones: {n_bones}");
+    println!("offset_bones: {offset_bones}");
+    println!("offset_names: {offset_names}");
+
+    // The binary data starts right after the header
+    let binary_offset = SKL_HEADER_SIZE as u64;
+
+    // Check if the offsets are sane
+    let number_of_bytes = file_length(file)?.saturating_sub(binary_offset);
+    if offset_bones as u64 + n_bones as u64 * BoneBinary::SIZE as u64 > number_of_bytes
+        || offset_names as u64 > number_of_bytes
+    {
+        println!("Offsets are out of bounds! File is unsafe!");
+        return Ok(false);
+    }
+
+    // Print every bone
+    let mut buf_bone = [0u8; BoneBinary::SIZE];
+    for bone_index in 0..n_bones {
+        read_at(
+            file,
+            binary_offset + offset_bones as u64 + bone_index as u64 * BoneBinary::SIZE as u64,
+            &mut buf_bone,
+        )?;
+        let bone = BoneBinary::from_bytes(&buf_bone);
+        let Some(name) = read_string(
+            file,
+            binary_offset,
+            bone.offset_name as u64,
+            number_of_bytes,
+        )?
+        else {
+            println!("Name of bone {bone_index} is out of bounds! File is unsafe!");
+            return Ok(false);
+        };
+        println!("bones[{bone_index}]:");
+        println!("\tname: {name} (hash {:08X})", bone.name_hash);
+        match bone.parent {
+            NO_INDEX => println!("\tparent: none"),
+            parent => println!("\tparent: {parent}"),
+        }
+        match bone.submesh_index {
+            NO_INDEX => println!("\tsubmesh_index: none"),
+            submesh_index => println!("\tsubmesh_index: {submesh_index}"),
+        }
+        println!("\tposition: {:?}", bone.transform.position);
+        println!("\trotation: {:?}", bone.transform.rotation);
+        println!("\tscale: {:?}", bone.transform.scale);
+        if bone.name_hash != name_hash(&name) {
+            println!("Hash of bone {bone_index} doesn't match its name. Invalid file.");
+            return Ok(false);
+        }
+
+        // The game walks the bones in order, so a parent has to come before its children
+        if bone.parent != NO_INDEX && bone.parent >= bone_index {
+            println!("Bone {bone_index} comes before its parent. Invalid file.");
+            return Ok(false);
+        }
+    }
+
+    println!("File is ok.");
+
+    Ok(true)
+}
+
+/// Print the contents of a .anm file and check that all offsets stay inside the file.
+/// Returns `Ok(false)` if the file is not a valid .anm file.
+pub fn debug_anm<R: Read + Seek>(file: &mut R) -> Result<bool, ConvertError> {
+    println!("FANM file debug");
+
+    // Verify file magic
+    let mut file_magic = [0u8; 4];
+    read_at(file, 0, &mut file_magic)?;
+    match &file_magic == b"FANM" {
+        true => println!("File magic ok. (\"FANM\")"),
+        false => {
+            println!("File magic not ok. Invalid file.");
+            return Ok(false);
+        }
+    }
+
+    // Verify format version
+    let version = read_u32(file)?;
+    println!("version: {version}");
+    if version != ANM_VERSION {
+        println!("Unsupported version, expected {ANM_VERSION}. Invalid file.");
+        return Ok(false);
+    }
+
+    let n_clips = read_u32(file)?;
+    let fixed_point_frame_rate = read_u32(file)?;
+    let offset_clips = read_u32(file)?;
+    let offset_tracks = read_u32(file)?;
+    let offset_frames = read_u32(file)?;
+    let offset_names = read_u32(file)?;
+    println!("n_clips: {n_clips}");
+    println!(
+        "fixed_point_frame_rate: {} ({} frames per second)",
+        fixed_point_frame_rate,
+        fixed_point_frame_rate as f64 / 65536.0
+    );
+    println!("offset_clips: {offset_clips}");
+    println!("offset_tracks: {offset_tracks}");
+    println!("offset_frames: {offset_frames}");
+    println!("offset_names: {offset_names}");
+
+    // The binary data starts right after the header
+    let binary_offset = ANM_HEADER_SIZE as u64;
+
+    // Check if the offsets are sane
+    let number_of_bytes = file_length(file)?.saturating_sub(binary_offset);
+    if offset_clips as u64 + n_clips as u64 * ClipBinary::SIZE as u64 > number_of_bytes
+        || offset_tracks as u64 > number_of_bytes
+        || offset_frames as u64 > number_of_bytes
+        || offset_names as u64 > number_of_bytes
+    {
+        println!("Offsets are out of bounds! File is unsafe!");
+        return Ok(false);
+    }
+
+    // Print every clip, with the first frame of each of its tracks
+    let mut buf_clip = [0u8; ClipBinary::SIZE];
+    let mut buf_track = [0u8; TrackBinary::SIZE];
+    let mut buf_frame = [0u8; TransformPSX::SIZE];
+    for clip_index in 0..n_clips as u64 {
+        read_at(
+            file,
+            binary_offset + offset_clips as u64 + clip_index * ClipBinary::SIZE as u64,
+            &mut buf_clip,
+        )?;
+        let clip = ClipBinary::from_bytes(&buf_clip);
+        let Some(name) = read_string(
+            file,
+            binary_offset,
+            clip.offset_name as u64,
+            number_of_bytes,
+        )?
+        else {
+            println!("Name of clip {clip_index} is out of bounds! File is unsafe!");
+            return Ok(false);
+        };
+        println!("clips[{clip_index}]:");
+        println!("\tname: {name} (hash {:08X})", clip.name_hash);
+        println!("\tn_frames: {}", clip.n_frames);
+        println!("\tn_tracks: {}", clip.n_tracks);
+        if clip.name_hash != name_hash(&name) {
+            println!("Hash of clip {clip_index} doesn't match its name. Invalid file.");
+            return Ok(false);
+        }
+        if offset_tracks as u64
+            + (clip.first_track as u64 + clip.n_tracks as u64) * TrackBinary::SIZE as u64
+            > number_of_bytes
+        {
+            println!("Tracks of clip {clip_index} are out of bounds! File is unsafe!");
+            return Ok(false);
+        }
+
+        for track_index in clip.first_track as u64..clip.first_track as u64 + clip.n_tracks as u64 {
+            read_at(
+                file,
+                binary_offset + offset_tracks as u64 + track_index * TrackBinary::SIZE as u64,
+                &mut buf_track,
+            )?;
+            let track = TrackBinary::from_bytes(&buf_track);
+            let Some(node_name) = read_string(
+                file,
+                binary_offset,
+                track.offset_name as u64,
+                number_of_bytes,
+            )?
+            else {
+                println!("Name of track {track_index} is out of bounds! File is unsafe!");
+                return Ok(false);
+            };
+            if track.name_hash != name_hash(&node_name) {
+                println!("Hash of track {track_index} doesn't match its name. Invalid file.");
+                return Ok(false);
+            }
+            if track.offset_frames as u64 + clip.n_frames as u64 * TransformPSX::SIZE as u64
+                > number_of_bytes
+            {
+                println!("Frames of track {track_index} are out of bounds! File is unsafe!");
+                return Ok(false);
+            }
+            match track.bone_index {
+                NO_INDEX => println!("\ttracks[{track_index}]: {node_name}"),
+                bone_index => println!("\ttracks[{track_index}]: {node_name} (bone {bone_index})"),
+            }
+            match track.submesh_index {
+                NO_INDEX => println!("\t\tsubmesh_index: none"),
+                submesh_index => println!("\t\tsubmesh_index: {submesh_index}"),
+            }
+            if clip.n_frames > 0 {
+                read_at(
+                    file,
+                    binary_offset + track.offset_frames as u64,
+                    &mut buf_frame,
+                )?;
+                let frame = TransformPSX::from_bytes(&buf_frame);
+                println!("\t\tfirst frame position: {:?}", frame.position);
+                println!("\t\tfirst frame rotation: {:?}", frame.rotation);
+                println!("\t\tfirst frame scale: {:?}", frame.scale);
+            }
+        }
+    }
+
+    println!("File is ok.");
+
+    Ok(true)
+}
+
+/// Print the contents of a .vta file and check that all offsets stay inside the file.
+/// Returns `Ok(false)` if the file is not a valid .vta file.
+pub fn debug_vta<R: Read + Seek>(file: &mut R) -> Result<bool, ConvertError> {
+    println!("FVTA file debug");
+
+    // Verify file magic
+    let mut file_magic = [0u8; 4];
+    read_at(file, 0, &mut file_magic)?;
+    match &file_magic == b"FVTA" {
+        true => println!("File magic ok. (\"FVTA\")"),
+        false => {
+            println!("File magic not ok. Invalid file.");
+            return Ok(false);
+        }
+    }
+
+    // Verify format version
+    let version = read_u32(file)?;
+    println!("version: {version}");
+    if version != VTA_VERSION {
+        println!("Unsupported version, expected {VTA_VERSION}. Invalid file.");
+        return Ok(false);
+    }
+
+    let n_sequences = read_u32(file)?;
+    let fixed_point_frame_rate = read_u32(file)?;
+    let offset_sequences = read_u32(file)?;
+    let offset_deltas = read_u32(file)?;
+    let offset_names = read_u32(file)?;
+    println!("n_sequences: {n_sequences}");
+    println!(
+        "fixed_point_frame_rate: {} ({} frames per second)",
+        fixed_point_frame_rate,
+        fixed_point_frame_rate as f64 / 65536.0
+    );
+    println!("offset_sequences: {offset_sequences}");
+    println!("offset_deltas: {offset_deltas}");
+    println!("offset_names: {offset_names}");
+
+    // The binary data starts right after the header
+    let binary_offset = VTA_HEADER_SIZE as u64;
+
+    // Check if the offsets are sane
+    let number_of_bytes = file_length(file)?.saturating_sub(binary_offset);
+    if offset_sequences as u64 + n_sequences as u64 * SequenceBinary::SIZE as u64 > number_of_bytes
+        || offset_deltas as u64 > number_of_bytes
+        || offset_names as u64 > number_of_bytes
+    {
+        println!("Offsets are out of bounds! File is unsafe!");
+        return Ok(false);
+    }
+
+    // Print every sequence, with the largest offset in it
+    let mut buf_sequence = [0u8; SequenceBinary::SIZE];
+    for sequence_index in 0..n_sequences as u64 {
+        read_at(
+            file,
+            binary_offset + offset_sequences as u64 + sequence_index * SequenceBinary::SIZE as u64,
+            &mut buf_sequence,
+        )?;
+        let sequence = SequenceBinary::from_bytes(&buf_sequence);
+        let Some(name) = read_string(
+            file,
+            binary_offset,
+            sequence.offset_name as u64,
+            number_of_bytes,
+        )?
+        else {
+            println!("Name of sequence {sequence_index} is out of bounds! File is unsafe!");
+            return Ok(false);
+        };
+        println!("sequences[{sequence_index}]:");
+        println!("\tname: {name} (hash {:08X})", sequence.name_hash);
+        println!("\tsubmesh_index: {}", sequence.submesh_index);
+        println!("\tn_vertices: {}", sequence.n_vertices);
+        println!("\tn_frames: {}", sequence.n_frames);
+        println!(
+            "\tanimated: {}",
+            sequence.flags & SEQUENCE_ANIMATED == SEQUENCE_ANIMATED
+        );
+        if sequence.name_hash != name_hash(&name) {
+            println!("Hash of sequence {sequence_index} doesn't match its name. Invalid file.");
+            return Ok(false);
+        }
+        let deltas_size = sequence.n_vertices as u64 * sequence.n_frames as u64 * 6;
+        if sequence.offset_deltas as u64 + deltas_size > number_of_bytes {
+            println!("Offsets of sequence {sequence_index} are out of bounds! File is unsafe!");
+            return Ok(false);
+        }
+        let mut buf_deltas = vec![0u8; deltas_size as usize];
+        read_at(
+            file,
+            binary_offset + sequence.offset_deltas as u64,
+            &mut buf_deltas,
+        )?;
+        let largest = buf_deltas
+            .chunks_exact(2)
+            .map(|bytes| i16_at(bytes, 0).unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        println!("\tlargest offset: {largest}");
+    }
+
+    println!("File is ok.");
+
+    Ok(true)
+}
+
+/// Print the contents of a .txc file and check that all offsets stay inside the file.
+/// If `png_out_dir` is set, every texture cell is also exported to `texture<i>.png` in that directory.
+/// Returns `Ok(false)` if the file is not a valid .txc file.
+pub fn debug_txc<R: Read + Seek>(
+    file: &mut R,
+    png_out_dir: Option<&Path>,
+) -> Result<bool, ConvertError> {
+    println!("FTXC file debug");
+
+    // Verify file magic
+    let mut file_magic = [0u8; 4];
+    read_at(file, 0, &mut file_magic)?;
+    match &file_magic == b"FTXC" {
+        true => println!("File magic ok. (\"FTXC\")"),
+        false => {
+            println!("File magic not ok. Invalid file.");
+            return Ok(false);
+        }
+    }
+
+    // Get n_texture_cell
+    let n_texture_cell = read_u32(file)?;
+    let offset_texture_cell_descs = read_u32(file)?;
+    let offset_palettes = read_u32(file)?;
+    let offset_textures = read_u32(file)?;
+    let offset_name_table = read_u32(file)?;
+
+    println!("n_texture_cell: {}", n_texture_cell);
+    println!("offset_texture_cell_descs: {}", offset_texture_cell_descs);
+    println!("offset_palettes: {}", offset_palettes);
+    println!("offset_textures: {}", offset_textures);
+    println!("offset_name_table: {}", offset_name_table);
+
+    // Validate offsets
+    let binary_offset = 24;
+    let number_of_bytes = file_length(file)?.saturating_sub(binary_offset);
+    if offset_texture_cell_descs as u64 + n_texture_cell as u64 * 8 > number_of_bytes
+        || offset_textures as u64 > number_of_bytes
+        || offset_palettes as u64 + n_texture_cell as u64 * 32 * 16 > number_of_bytes
+        || ((offset_name_table != 0xFFFFFFFFu32) && (offset_name_table as u64 > number_of_bytes))
+    {
+        println!("Offsets are out of bounds! File is unsafe!");
+        return Ok(false);
+    }
+
+    // Debug each texture cell
+    for i in 0..n_texture_cell {
+        // Get the texture cell
+        let mut buf64 = [0u8; 8];
+        read_at(
+            file,
+            binary_offset + offset_texture_cell_descs as u64 + (i as u64 * 8),
+            &mut buf64,
+        )?;
+        let texture_cell = TextureCellBinary::from_bytes(&buf64);
+
+        // Print the data
+        println!(
+            "Texture {}: offset: {}\tresolution: {}x{}, \tpalette_index: {},\tavg_color: {:08X}",
+            i,
+            texture_cell.sector_offset_texture as u32 * 2048,
+            texture_cell.texture_width,
+            texture_cell.texture_height,
+            texture_cell.palette_index,
+            texture_cell.avg_color
+        );
+
+        // Make sure the texture data and palette are inside the file
+        let width = texture_cell.texture_width as usize;
+        let height = texture_cell.texture_height as usize;
+        let texture_offset =
+            offset_textures as u64 + texture_cell.sector_offset_texture as u64 * 2048;
+        let palette_offset = offset_palettes as u64 + (texture_cell.palette_index as u64 * 32 * 16);
+        if texture_offset + (width * height / 2) as u64 > number_of_bytes
+            || palette_offset + 32 > number_of_bytes
+        {
+            println!("Texture {i} is out of bounds! File is unsafe!");
+            return Ok(false);
+        }
+
+        let Some(png_out_dir) = png_out_dir else {
+            continue;
+        };
+
+        // Read the texture data
+        let mut texture_indices_4bit = vec![0u8; width * height / 2];
+        read_at(
+            file,
+            binary_offset + texture_offset,
+            &mut texture_indices_4bit,
+        )?;
+
+        // Read the palette data
+        let mut palette_16bit = [0u8; 32];
+        read_at(file, binary_offset + palette_offset, &mut palette_16bit)?;
+
+        // Loop over each pixel
+        let mut pixels = RgbaImage::new(width as u32, height as u32);
+        for y in 0..height {
+            for x in 0..width {
+                let mut pixel = texture_indices_4bit[(x + (y * width)) / 2];
+
+                // Extract the 4-bit index from the byte
+                if (x % 2) == 0 {
+                    pixel = (pixel & 0xF0) >> 4;
+                } else {
+                    pixel &= 0x0F;
+                }
+
+                // Look up the color in the palette
+                let color = palette_16bit[pixel as usize * 2 + 0] as u16
+                    + ((palette_16bit[pixel as usize * 2 + 1] as u16) << 8);
+
+                // Convert to 32 bit color
+                let r = 8 * ((color >> 0) & 0x1F) as u8;
+                let g = 8 * ((color >> 5) & 0x1F) as u8;
+                let b = 8 * ((color >> 10) & 0x1F) as u8;
+                let a = 255 * ((color >> 15) & 0x01) as u8;
+
+                pixels.put_pixel(x as u32, y as u32, Rgba([r, g, b, a]));
+            }
+        }
+
+        // Export the image
+        let output = DynamicImage::ImageRgba8(pixels);
+        output
+            .save(png_out_dir.join(format!("texture{i}.png")))
+            .map_err(|err| ConvertError::texture(format!("texture {i}"), err.to_string()))?;
+    }
+
+    println!("File is ok.");
+
+    Ok(true)
+}
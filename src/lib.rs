@@ -0,0 +1,27 @@
+#![allow(clippy::identity_op, clippy::too_many_arguments)]
+
+pub mod animation;
+pub mod axes;
+pub mod convert;
+pub mod error;
+pub mod helpers;
+pub mod inspect;
+pub mod mesh;
+pub mod partition;
+pub mod psx_structs;
+pub mod scene;
+pub mod structs;
+pub mod texture;
+
+pub use axes::AxisMapping;
+pub use convert::{
+    convert_gltf, convert_gltf_scenes, convert_gltf_slice, export_msh, export_scenes, is_gltf_path,
+    output_base_path, save_outputs, ConvertOptions, ConvertOutput, Dither, Scale,
+};
+pub use error::ConvertError;
+pub use partition::Partition;
+pub use psx_structs::{
+    AnimationCollectionPSX, EntityCollectionPSX, ModelPSX, SkeletonPSX, TextureCollectionPSX,
+    VertexAnimationPSX,
+};
+pub use scene::SceneRef;
@@ -1,414 +1,302 @@
-#![allow(clippy::identity_op, clippy::too_many_arguments, dead_code)]
-
 use std::{
     fs::File,
-    io::{Read, Seek},
-    os::windows::prelude::FileExt,
-    path::Path, iter::Map, collections::HashMap,
+    io::Cursor,
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
 
-use exoquant::{convert_to_indexed, ditherer, optimizer, Color};
+use clap::{Args, Parser, Subcommand};
 use glam::Vec3;
-use helpers::validate;
-use mesh::Model;
-use psx_structs::{MeshPSX, TextureCollectionPSX};
-
-use crate::{
-    psx_structs::{MeshDesc, ModelPSX, TextureCellBinary, TextureCellPSX, VertexPSX},
-    texture::Material,
+use gltf2psx::{
+    convert_gltf, export_msh, export_scenes,
+    inspect::{debug_anm, debug_ent, debug_msh, debug_skl, debug_txc, debug_vta},
+    is_gltf_path, output_base_path,
+    partition::PartitionKind,
+    scene::NodeFilter,
+    AxisMapping, ConvertError, ConvertOptions, Dither, Partition, Scale, SceneRef,
 };
 
-mod helpers;
-mod mesh;
-mod psx_structs;
-mod structs;
-mod texture;
-use image::{RgbaImage, DynamicImage, Rgba};
-const DEBUG_VIEW: bool = false;
-
-fn main() {
-    // Get command line arguments and check if we have one
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        return;
-    }
-    let path_in = args[1].clone();
-
-    // If it's a glTF, load it and export a .msh file
-    if path_in.ends_with(".gltf") {
-        let path_out = path_in.replace(".gltf", "");
-        export_msh(path_in, path_out);
-        return;
-    }
-
-    // If it's a .msh file, debug it
-    if path_in.ends_with(".msh") {
-        debug_msh(path_in);
-        return;
-    }
-
-    // If it's a .msh file, debug it
-    if path_in.ends_with(".txc") {
-        debug_txc(path_in);
-    }
+/// Convert glTF models to the .msh and .txc formats used by PSX projects.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn export_msh(path_in: String, path_out: String) {
-    // Load the glTF
-    let mut model = Model::new();
-    model.create_from_gltf(Path::new(path_in.as_str()));
-
-    // Prepare PSX output model
-    let mut model_psx_out = ModelPSX::new();
-    let mut txc_psx_out = TextureCollectionPSX::new();
-
-    // Make a map based on a grid
-    let grid_size = (1.75, 50000.0, 1.75);
-    let mut mesh_grid: HashMap<i128, MeshPSX> = HashMap::new();
-
-    // Loop over each submesh in the model
-    for (texture_id, (material_name, mesh)) in model.meshes.into_iter().enumerate() {
-        // Create PSX mesh for this submesh
-        {
-            // Convert each triangle to a PSX triangle
-            for triangle in mesh.verts.chunks(3) {
-                // Find which gridcell this triangle belongs to
-                let average_position = (triangle[0].position + triangle[1].position + triangle[2].position) / 3.0;
-                let grid_x = (average_position.x / grid_size.0).round() as i32;
-                let grid_y = (average_position.y / grid_size.1).round() as i32;
-                let grid_z = (average_position.z / grid_size.2).round() as i32;
-                let map_entry = (grid_x as i128) | (grid_y as i128) << 32 | (grid_z as i128) << 64;
-
-                // Create entry in grid map if it didn't exist yet
-                if mesh_grid.get(&map_entry).is_none() {
-                    mesh_grid.insert(map_entry, MeshPSX::new());
-                }
-
-                // Add this triangle to that mesh
-                let mesh_psx = mesh_grid.get_mut(&map_entry).unwrap();
-                mesh_psx.verts.push(VertexPSX::from(&triangle[0], texture_id as u8));
-                mesh_psx.verts.push(VertexPSX::from(&triangle[1], texture_id as u8));
-                mesh_psx.verts.push(VertexPSX::from(&triangle[2], texture_id as u8));
-            }
-        }
-
-        // Create PSX texture collection for this submesh
-        {
-            // Retrieve material corresponding to this submesh
-            let mat: &Material = &model.materials[&material_name];
-
-            // For debug purposes, export the textures
-            if DEBUG_VIEW {
-                let mut pixels = Vec::new();
-                for value in &mat.texture.data {
-                    pixels.push(((value >> 0) & 0xFF) as u8);
-                    pixels.push(((value >> 8) & 0xFF) as u8);
-                    pixels.push(((value >> 16) & 0xFF) as u8);
-                    pixels.push(((value >> 24) & 0xFF) as u8);
-                }
-                let image_data = RgbaImage::from_vec(64, 64, pixels).unwrap();
-                let output = DynamicImage::ImageRgba8(image_data);
-                output.save(format!("{material_name}.png")).unwrap();
-            }
-
-            // Create texture cell object
-            let mut tex_cell = TextureCellPSX {
-                texture_data: Vec::new(),
-                palette: Vec::new(),
-                texture_width: mat.texture.width as u8,
-                texture_height: mat.texture.height as u8,
-                avg_color: 0,
-            };
-
-            // Quantize it to 16 colours
-            let mut tex_data_exoquant = Vec::new();
-            let tex_data_src = &mat.texture.data;
-            for pixel in tex_data_src {
-                let pixel8 = pixel.to_be_bytes();
-                tex_data_exoquant.push(exoquant::Color::new(
-                    pixel8[3], pixel8[2], pixel8[1], pixel8[0],
-                ));
-            }
-            let (palette, indexed_data) = convert_to_indexed(
-                &tex_data_exoquant,
-                mat.texture.width,
-                16,
-                &optimizer::KMeans,
-                &ditherer::Ordered,
-            );
-            let color_b = Color {
-                r: (mat.texture.avg_color & 0x000000FF >> 0) as u8,
-                g: (mat.texture.avg_color & 0x0000FF00 >> 8) as u8,
-                b: (mat.texture.avg_color & 0x00FF0000 >> 16) as u8,
-                a: (mat.texture.avg_color & 0xFF000000 >> 24) as u8,
-            };
-            for fade_level in 0..16 {
-                for color in &palette {
-                    let color: u16 = (color.a as u16).clamp(0, 1) << 15
-                        | ((((fade_level * color_b.b as u16)
-                            + ((15 - fade_level) * color.b as u16))
-                            / 15)
-                            >> 3)
-                            .clamp(0, 31)
-                            << 10
-                        | ((((fade_level * color_b.g as u16)
-                            + ((15 - fade_level) * color.g as u16))
-                            / 15)
-                            >> 3)
-                            .clamp(0, 31)
-                            << 5
-                        | ((((fade_level * color_b.r as u16)
-                            + ((15 - fade_level) * color.r as u16))
-                            / 15)
-                            >> 3)
-                            .clamp(0, 31)
-                            << 0;
-                    tex_cell.palette.push(color);
-                }
-            }
-
-            // Convert indices to 4 bit
-            println!("{:?}", indexed_data.len());
-            for i in (0..(mat.texture.width * mat.texture.height)).step_by(2) {
-                if (i + 1) < indexed_data.len() {
-                    tex_cell
-                        .texture_data
-                        .push((indexed_data[i + 0] << 4) | (indexed_data[i + 1]));
-                } else {
-                    tex_cell.texture_data.push(0);
-                    tex_cell.texture_data.push(0);
-                    tex_cell.texture_data.push(0);
-                    tex_cell.texture_data.push(0);
-                }
-            }
+#[derive(Subcommand)]
+enum Command {
+    /// Convert a glTF file to a .msh and .txc file
+    Convert {
+        /// The glTF file to convert
+        input: PathBuf,
+        /// Directory to write the output files to. Defaults to the directory of the input file
+        #[arg(long)]
+        out_dir: Option<PathBuf>,
+        /// Base name of the output files. Defaults to the name of the input file
+        #[arg(long)]
+        name: Option<String>,
+        /// Convert every scene in the file, each to its own "<name>_<scene>.msh" and .txc file
+        #[arg(long, conflicts_with = "scene")]
+        all_scenes: bool,
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+    /// Print the contents of a .msh, .txc, .ent, .skl, .anm or .vta file and check if it's valid
+    Inspect {
+        /// The .msh, .txc, .ent, .skl, .anm or .vta file to inspect
+        file: PathBuf,
+    },
+    /// Export every texture in a .txc file to a PNG file
+    ExtractTextures {
+        /// The .txc file to extract the textures from
+        file: PathBuf,
+        /// Directory to write the PNG files to
+        #[arg(long, default_value = ".")]
+        out_dir: PathBuf,
+    },
+    /// Convert a glTF file in memory and check the result, without writing any files
+    Validate {
+        /// The glTF file to validate
+        input: PathBuf,
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+}
 
-            // Set average color in cell
-            tex_cell.avg_color = mat.texture.avg_color;
+#[derive(Args)]
+struct OptionArgs {
+    /// Name or index of the scene to convert. Defaults to the default scene, or the first scene
+    #[arg(long)]
+    scene: Option<SceneRef>,
+    /// How to split the model into submeshes: "none", "grid", "octree" or "kd"
+    #[arg(long, default_value = "grid")]
+    partition: PartitionKind,
+    /// Size of a grid cell for the "grid" partition, as "x,y,z" in glTF units
+    #[arg(long, value_parser = parse_vec3, default_value = "1.75,50000,1.75")]
+    grid_size: Vec3,
+    /// Maximum number of triangles in a submesh for the "octree" and "kd" partitions
+    #[arg(long, default_value_t = 256)]
+    max_triangles: usize,
+    /// Maximum number of times a cell gets split for the "octree" partition, at most 32
+    #[arg(long, default_value_t = 8)]
+    max_depth: u32,
+    /// Number of fixed-point units per glTF unit, or "auto" to use the largest scale that fits the model
+    #[arg(long, default_value = "1024")]
+    scale: Scale,
+    /// Output axes: "y-down", "y-up", "z-up", or a mapping from glTF axes like "-x,-y,+z"
+    #[arg(long, default_value = "y-down")]
+    axes: AxisMapping,
+    /// Store the vertex positions of each submesh relative to its center, to keep precision in big levels
+    #[arg(long)]
+    relative_origins: bool,
+    /// Keep nodes with a matching name as their own submesh, and list them in the name table.
+    /// Can be given more than once, and `*` and `?` can be used as wildcards
+    #[arg(long = "keep-node", value_name = "PATTERN")]
+    keep_nodes: Vec<String>,
+    /// Only convert nodes with a matching name, and their children. Can be given more than once
+    #[arg(long, value_name = "PATTERN")]
+    include: Vec<String>,
+    /// Skip nodes with a matching name, and their children. Can be given more than once
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+    /// Convert each glTF mesh once, and write the nodes that use it to an instance table instead of
+    /// baking them into the model. Ignores --partition and --keep-node
+    #[arg(long)]
+    instancing: bool,
+    /// Only export extras keys with a matching name to the entity file. Can be given more than once.
+    /// Every key is exported by default
+    #[arg(long = "entity-property", value_name = "PATTERN")]
+    entity_properties: Vec<String>,
+    /// Number of frames per second to resample the animations and morph target weights at
+    #[arg(long, default_value_t = 30.0)]
+    frame_rate: f32,
+    /// Number of colors in each texture palette
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u8).range(1..=16))]
+    palette_size: u8,
+    /// Dithering used while quantizing textures: "none", "ordered" or "floyd-steinberg"
+    #[arg(long, default_value = "ordered")]
+    dither: Dither,
+    /// Export the source texture of each material as a PNG file in the working directory
+    #[arg(long)]
+    debug_view: bool,
+}
 
-            // Add this cell to the collection
-            txc_psx_out.texture_cells.push(tex_cell);
-            txc_psx_out.texture_names.push(material_name);
+impl OptionArgs {
+    fn to_options(&self) -> ConvertOptions {
+        ConvertOptions {
+            scene: self.scene.clone(),
+            partition: match self.partition {
+                PartitionKind::None => Partition::None,
+                PartitionKind::Grid => Partition::Grid(self.grid_size),
+                PartitionKind::Octree => Partition::Octree {
+                    max_triangles: self.max_triangles,
+                    max_depth: self.max_depth,
+                },
+                PartitionKind::KdTree => Partition::KdTree {
+                    max_triangles: self.max_triangles,
+                },
+            },
+            scale: self.scale,
+            axes: self.axes,
+            relative_origins: self.relative_origins,
+            keep_nodes: self.keep_nodes.clone(),
+            node_filter: NodeFilter {
+                include: self.include.clone(),
+                exclude: self.exclude.clone(),
+            },
+            instancing: self.instancing,
+            entity_properties: self.entity_properties.clone(),
+            frame_rate: self.frame_rate,
+            palette_size: self.palette_size as usize,
+            dither: self.dither,
+            debug_view: self.debug_view,
         }
     }
+}
 
-    // For every grid cell, put it in the model_psx
-    for (_, mesh) in mesh_grid {
-        model_psx_out.meshes.push(mesh);
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+    let values = s
+        .split(',')
+        .map(|value| value.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|err| err.to_string())?;
+    match values.as_slice() {
+        [x, y, z] => Ok(Vec3::new(*x, *y, *z)),
+        _ => Err(format!(
+            "expected 3 comma separated values, got {}",
+            values.len()
+        )),
     }
-
-    validate(model_psx_out.save(Path::new(&(path_out.clone() + ".msh"))));
-    validate(txc_psx_out.save(Path::new(&(path_out + ".txc"))));
 }
 
-fn debug_msh(path_in: String) -> bool {
-    let mut file = File::open(Path::new(path_in.as_str())).unwrap();
-    println!("FMSH file debug");
-
-    // Verify file magic
-    let mut buf32 = [0, 0, 0, 0];
+fn main() -> ExitCode {
+    let cli = Cli::parse();
 
-    validate(file.read(&mut buf32));
-    let file_magic = u32::from_be_bytes(buf32);
-    match file_magic != 0x424D9640 {
-        true => println!("File magic ok. (\"FMSH\")"),
-        false => {
-            println!("File magic not ok. Invalid file.");
-            return false;
+    match run(cli.command) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
         }
     }
+}
 
-    // Verify number of submeshes
-    validate(file.read(&mut buf32));
-    let n_submeshes = u32::from_le_bytes(buf32);
-    println!("n_submeshes: {n_submeshes}");
-
-    // Get mesh description offset
-    validate(file.read(&mut buf32));
-    let offset_mesh_desc = u32::from_le_bytes(buf32);
-    println!("offset_mesh_desc: {offset_mesh_desc}");
-
-    // Get vertex data offset
-    validate(file.read(&mut buf32));
-    let offset_vertex_data = u32::from_le_bytes(buf32);
-    println!("offset_vertex_data: {offset_vertex_data}");
-
-    // Get the current position - the binary data starts here
-    let binary_offset = 16;
-
-    // Check if the offsets are sane
-    let start_binary_section = file
-        .seek(std::io::SeekFrom::Start(
-            binary_offset + offset_vertex_data as u64,
-        ))
-        .unwrap();
-    let end_binary_section = file.seek(std::io::SeekFrom::End(0)).unwrap();
-    let number_of_bytes = end_binary_section.overflowing_sub(start_binary_section).0;
-    if offset_mesh_desc as u64 > number_of_bytes || offset_vertex_data as u64 > number_of_bytes {
-        println!("Offsets are out of bounds! File is unsafe!");
-        return false;
-    }
-
-    // Binary section starts after this
-    // First read all the mesh descriptions
-    let mut buf_mesh_desc = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
-
-    let mut lowest_vertex_index = 0u16;
-    let mut highest_vertex_index = 0u16;
+fn open(path: &Path) -> Result<File, ConvertError> {
+    File::open(path).map_err(|err| ConvertError::io(path, err))
+}
 
-    for submesh_index in 0..n_submeshes {
-        validate(file.seek_read(
-            &mut buf_mesh_desc,
-            binary_offset + offset_mesh_desc as u64 + (submesh_index * 16) as u64,
-        ));
-        let mesh_desc = MeshDesc::from_bytes(&buf_mesh_desc);
-        println!("mesh_descs[{submesh_index}]:");
-        println!("\tvertex_start: {}", mesh_desc.vertex_start);
-        println!("\tn_vertices: {}", mesh_desc.n_vertices);
-        println!("\tx_min, x_max: {}, {}", mesh_desc.x_min, mesh_desc.x_max);
-        println!("\ty_min, y_max: {}, {}", mesh_desc.y_min, mesh_desc.y_max);
-        println!("\tz_min, z_max: {}, {}", mesh_desc.z_min, mesh_desc.z_max);
-        lowest_vertex_index = lowest_vertex_index.min(mesh_desc.vertex_start);
-        highest_vertex_index =
-            highest_vertex_index.min(mesh_desc.vertex_start + mesh_desc.n_vertices);
+fn print_warnings(path: &Path, warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("warning: {}: {warning}", path.display());
     }
+}
 
-    // Check if vertex indices fit inside the binary section
-    if (lowest_vertex_index.max(highest_vertex_index) * 12) as u64 > number_of_bytes {
-        println!("Vertex data is out of bounds! File is unsafe!");
-        return false;
+fn check_gltf_path(path: &Path) -> Result<(), ConvertError> {
+    match is_gltf_path(path) {
+        true => Ok(()),
+        false => Err(
+            ConvertError::unsupported("", "input files that are not .gltf or .glb").with_path(path),
+        ),
     }
-
-    println!("File is ok.");
-
-    true
 }
 
-fn debug_txc(path_in: String) -> bool {
-    let mut file = File::open(Path::new(path_in.as_str())).unwrap();
-    println!("FTXC file debug");
-
-    // Verify file magic
-    let mut buf32 = [0, 0, 0, 0];
-
-    validate(file.read(&mut buf32));
-    let file_magic = u32::from_be_bytes(buf32);
-    match file_magic != 0x464D5348 {
-        true => println!("File magic ok. (\"FTXC\")"),
-        false => {
-            println!("File magic not ok. Invalid file.");
-            return false;
+/// Run a subcommand. Returns `Ok(false)` if an inspected file turned out to be invalid.
+fn run(command: Command) -> Result<bool, ConvertError> {
+    match command {
+        Command::Convert {
+            input,
+            out_dir,
+            name,
+            all_scenes,
+            options,
+        } => {
+            check_gltf_path(&input)?;
+
+            // Put the output next to the input file, with the same name, unless told otherwise
+            let path_out = output_base_path(&input, out_dir.as_deref(), name.as_deref());
+            if all_scenes {
+                for (_, output) in export_scenes(&input, &path_out, &options.to_options())? {
+                    print_warnings(&input, &output.warnings);
+                }
+            } else {
+                let output = export_msh(&input, &path_out, &options.to_options())?;
+                print_warnings(&input, &output.warnings);
+            }
+            Ok(true)
         }
-    }
-
-    // Get n_texture_cell
-    validate(file.read(&mut buf32));
-    let n_texture_cell = u32::from_le_bytes(buf32);
-    validate(file.read(&mut buf32));
-    let offset_texture_cell_descs = u32::from_le_bytes(buf32);
-    validate(file.read(&mut buf32));
-    let offset_palettes = u32::from_le_bytes(buf32);
-    validate(file.read(&mut buf32));
-    let offset_textures = u32::from_le_bytes(buf32);
-    validate(file.read(&mut buf32));
-    let offset_name_table = u32::from_le_bytes(buf32);
-
-    println!("n_texture_cell: {}", n_texture_cell);
-    println!("offset_texture_cell_descs: {}", offset_texture_cell_descs);
-    println!("offset_palettes: {}", offset_palettes);
-    println!("offset_textures: {}", offset_textures);
-    println!("offset_name_table: {}", offset_name_table);
-
-    // Validate offsets
-    let binary_offset = 24;
-    let start_binary_section = file.seek(std::io::SeekFrom::Start(binary_offset)).unwrap();
-    let end_binary_section = file.seek(std::io::SeekFrom::End(0)).unwrap();
-    let number_of_bytes = end_binary_section.overflowing_sub(start_binary_section).0;
-    if offset_texture_cell_descs as u64 > number_of_bytes
-        || offset_textures as u64 > number_of_bytes
-        || offset_palettes as u64 > number_of_bytes
-        || ((offset_name_table != 0xFFFFFFFFu32) && (offset_name_table as u64 > number_of_bytes))
-    {
-        println!("Offsets are out of bounds! File is unsafe!");
-        return false;
-    }
-
-    // Debug each texture cell
-    for i in 0..n_texture_cell {
-        // Find texture cell
-        let _ = file
-            .seek(std::io::SeekFrom::Start(
-                binary_offset + offset_texture_cell_descs as u64 + (i as u64 * 8),
-            ))
-            .unwrap();
-
-        // Get the data
-        let mut buf64 = [0u8; 8];
-        validate(file.read(&mut buf64));
-        let texture_cell = TextureCellBinary::from_bytes(&buf64);
-
-        // Print the data
-        println!(
-            "Texture {}: offset: {}\tresolution: {}x{}, \tpalette_index: {},\tavg_color: {:08X}",
-            i,
-            texture_cell.sector_offset_texture as u32 * 2048,
-            texture_cell.texture_width,
-            texture_cell.texture_height,
-            texture_cell.palette_index,
-            texture_cell.avg_color
-        );
-
-        // Find the texture data
-        let _ = file
-            .seek(std::io::SeekFrom::Start(
-                binary_offset + offset_textures as u64 + (i as u64 * 2048),
-            ))
-            .unwrap();
-
-        // Read the texture data
-        let mut texture_indices_4bit = [0u8; 64*64/2];
-        validate(file.read(&mut texture_indices_4bit));
-
-        // Find the palette data
-        let _ = file
-            .seek(std::io::SeekFrom::Start(
-                binary_offset + offset_palettes as u64 + (i as u64 * 32 * 16),
-            ))
-            .unwrap();
-
-        // Read the palette data
-        let mut palette_16bit = [0u8; 32];
-        validate(file.read(&mut palette_16bit));
-
-        // Loop over each pixel
-        let mut pixels = RgbaImage::new(64, 64);
-        for y in 0..64 {
-            for x in 0..64 {
-                let mut pixel = texture_indices_4bit[(x + (y * 64)) / 2];
-
-                // Extract the 4-bit index from the byte
-                if (x % 2) == 0 {
-                    pixel = (pixel & 0xF0) >> 4;
-                } else {
-                    pixel = pixel & 0x0F;
+        Command::Inspect { file } => {
+            let extension = file.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+            let result = match extension {
+                "msh" => debug_msh(&mut open(&file)?),
+                "txc" => debug_txc(&mut open(&file)?, None),
+                "ent" => debug_ent(&mut open(&file)?),
+                "skl" => debug_skl(&mut open(&file)?),
+                "anm" => debug_anm(&mut open(&file)?),
+                "vta" => debug_vta(&mut open(&file)?),
+                _ => {
+                    eprintln!(
+                        "error: {}: not a .msh, .txc, .ent, .skl, .anm or .vta file",
+                        file.display()
+                    );
+                    return Ok(false);
                 }
-
-                // Look up the color in the palette
-                let color = palette_16bit[pixel as usize * 2 + 0] as u16 + ((palette_16bit[pixel as usize * 2 + 1] as u16) << 8);
-
-                // Convert to 32 bit color
-                let r = 8 * ((color >> 0) & 0x1F) as u8;
-                let g = 8 * ((color >> 5) & 0x1F) as u8;
-                let b = 8 * ((color >> 10) & 0x1F) as u8;
-                let a = 255 * ((color >> 15) & 0x01) as u8;
-
-                pixels.put_pixel(x as u32, y as u32, Rgba([r, g, b, a]));
+            };
+            result.map_err(|err| err.with_path(&file))
+        }
+        Command::ExtractTextures { file, out_dir } => {
+            debug_txc(&mut open(&file)?, Some(&out_dir)).map_err(|err| err.with_path(&file))
+        }
+        Command::Validate { input, options } => {
+            check_gltf_path(&input)?;
+
+            // Convert the model, then check the files we would have written
+            let output = convert_gltf(&input, &options.to_options())?;
+            print_warnings(&input, &output.warnings);
+            let msh = output
+                .model
+                .to_bytes()
+                .map_err(|err| err.with_path(&input))?;
+            let txc = output
+                .textures
+                .to_bytes()
+                .map_err(|err| err.with_path(&input))?;
+            let ent = output
+                .entities
+                .to_bytes()
+                .map_err(|err| err.with_path(&input))?;
+            let msh_ok = debug_msh(&mut Cursor::new(msh))?;
+            let txc_ok = debug_txc(&mut Cursor::new(txc), None)?;
+            let ent_ok = debug_ent(&mut Cursor::new(ent))?;
+
+            // Only skinned models get a skeleton file, only animated scenes an animation file, and only
+            // models with morph targets a vertex animation file
+            let mut skl_ok = true;
+            if !output.skeleton.bones.is_empty() {
+                let skl = output
+                    .skeleton
+                    .to_bytes()
+                    .map_err(|err| err.with_path(&input))?;
+                skl_ok = debug_skl(&mut Cursor::new(skl))?;
             }
+            let mut anm_ok = true;
+            if !output.animations.clips.is_empty() {
+                let anm = output
+                    .animations
+                    .to_bytes()
+                    .map_err(|err| err.with_path(&input))?;
+                anm_ok = debug_anm(&mut Cursor::new(anm))?;
+            }
+            let mut vta_ok = true;
+            if !output.vertex_animations.sequences.is_empty() {
+                let vta = output
+                    .vertex_animations
+                    .to_bytes()
+                    .map_err(|err| err.with_path(&input))?;
+                vta_ok = debug_vta(&mut Cursor::new(vta))?;
+            }
+            Ok(msh_ok && txc_ok && ent_ok && skl_ok && anm_ok && vta_ok)
         }
-
-        // Export the image
-        let output = DynamicImage::ImageRgba8(pixels);
-        output.save(format!("texture{i}.png")).unwrap();
     }
-
-    true
 }
@@ -1,22 +1,114 @@
-use std::{collections::HashMap, path::Path};
+use std::{collections::BTreeMap, path::Path};
 
 use glam::Vec4Swizzles;
-use glam::{Mat4, Vec2, Vec3, Vec4};
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+use serde_json::{Map, Value};
 
+use gltf::accessor::sparse::IndexType;
+use gltf::animation::Interpolation;
 use gltf::buffer::Data;
+use gltf::mesh::Mode;
 use gltf::texture::{MagFilter, MinFilter, WrappingMode};
 
+use crate::animation::{Animation, Channel, Property};
+use crate::error::ConvertError;
+use crate::scene::{find_scene, node_extras, NodeFilter, SceneRef};
 use crate::structs::Transform;
 use crate::texture::{FilterMode, Material, Sampler, WrapMode};
 use crate::{structs::Vertex, texture::Texture};
 
+#[derive(Default)]
 pub struct Mesh {
     pub verts: Vec<Vertex>,
+    pub nodes: Vec<usize>,      // The glTF node index each triangle came from
+    pub skinned: Vec<bool>, // Whether each triangle was rigidly skinned, and is in the local space of its bone
+    pub joints: Vec<usize>, // The skin joint each vertex is weighted to most, only used while importing
+    pub morphs: Vec<Vec<Vec3>>, // The offset of each vertex for every morph target of its node
 }
 
+impl Mesh {
+    /// Only keep the triangles for which `keep` returns true, given their node and whether they're skinned.
+    fn retain_triangles(&mut self, keep: impl Fn(usize, bool) -> bool) {
+        let kept: Vec<bool> = self
+            .nodes
+            .iter()
+            .zip(&self.skinned)
+            .map(|(node, skinned)| keep(*node, *skinned))
+            .collect();
+        let mut vertex = 0;
+        self.verts.retain(|_| {
+            vertex += 1;
+            kept[(vertex - 1) / 3]
+        });
+        let mut vertex = 0;
+        self.morphs.retain(|_| {
+            vertex += 1;
+            kept[(vertex - 1) / 3]
+        });
+        let mut triangle = kept.iter();
+        self.nodes.retain(|_| *triangle.next().unwrap());
+        let mut triangle = kept.iter();
+        self.skinned.retain(|_| *triangle.next().unwrap());
+    }
+}
+
+#[derive(Default)]
 pub struct Model {
-    pub meshes: HashMap<String, Mesh>, // Where the String is the material id
-    pub materials: HashMap<String, Material>, // Where the String is the material id
+    // Keyed by glTF material index, None is the default material. These are ordered maps, so
+    // the materials always come out in the same order: the default material first, then the
+    // rest in glTF order
+    pub meshes: BTreeMap<Option<usize>, Mesh>,
+    pub materials: BTreeMap<Option<usize>, Material>,
+    pub node_names: Vec<String>,          // Indexed by glTF node index
+    pub node_parents: Vec<Option<usize>>, // Indexed by glTF node index
+    pub warnings: Vec<String>,            // Problems that were found while importing
+    pub instances: Vec<Instance>,         // Nodes whose mesh is stored in local space
+    pub entities: Vec<Entity>,            // Nodes without a mesh, in the order they were visited
+    pub bones: Vec<Bone>,                 // Skin joints in the scene, parents first
+    pub node_transforms: Vec<Transform>,  // Indexed by glTF node index, without animation
+    pub animations: Vec<Animation>,       // Only the channels that move nodes in the scene
+    pub morphs: Vec<Morph>, // Nodes with morph targets, in the order they were visited
+}
+
+/// A joint of a skin. Rigidly skinned triangles are stored in the local space of their bone.
+pub struct Bone {
+    pub node: usize,
+    /// Index of the closest parent node that is also a bone.
+    pub parent: Option<usize>,
+    /// Transform of the bone in the bind pose, in model space.
+    pub bind: Mat4,
+}
+
+/// A node without a mesh, which gets exported as a gameplay entity.
+pub struct Entity {
+    pub node: usize,
+    pub transform: Mat4,
+    pub extras: Map<String, Value>,
+}
+
+/// A node whose mesh has morph targets. The offsets themselves are stored per vertex, in `Mesh::morphs`.
+pub struct Morph {
+    pub node: usize,
+    /// Name of each morph target, from the `targetNames` extras of the mesh.
+    pub target_names: Vec<String>,
+    /// The weight of each morph target when it isn't animated.
+    pub weights: Vec<f32>,
+}
+
+/// A node whose mesh is stored in its local space. When importing with instancing that's every node with a
+/// mesh, otherwise only the ones an animation moves, so the animation can place them.
+pub struct Instance {
+    pub mesh: usize,
+    pub node: usize,
+    /// The node the geometry of the mesh was converted for. Its triangles are tagged with this node.
+    pub prototype: usize,
+    pub transform: Mat4,
+}
+
+// The sparse accessor counts and offsets are u32 in older versions of the gltf crate, and usize in newer ones.
+// Anything that doesn't fit becomes usize::MAX, which fails the bounds checks
+fn to_usize<T: TryInto<usize>>(value: T) -> usize {
+    value.try_into().unwrap_or(usize::MAX)
 }
 
 // So what this function needs to do: &[u8] -(reinterpret)> &[SrcCompType] -(convert)> &[DstCompType]
@@ -25,19 +117,12 @@ where
     DstCompType: From<SrcCompType>,
     SrcCompType: Copy,
 {
-    // &[u8] -> &[SrcCompType]
-    let input_ptr = input_buffer.as_ptr();
-    let src_comp_buffer: &[SrcCompType] = unsafe {
-        std::slice::from_raw_parts(
-            std::mem::transmute(input_ptr),
-            input_buffer.len() / std::mem::size_of::<SrcCompType>(),
-        )
-    };
-
-    // &[SrcCompType] -> Vec<DstCompType>
+    // &[u8] -> SrcCompType -> DstCompType, one component at a time
+    // The components don't have to be aligned in the buffer, so they're read unaligned
     let mut dst_comp_vec = Vec::<DstCompType>::new();
-    for item in src_comp_buffer {
-        dst_comp_vec.push(DstCompType::from(*item));
+    for chunk in input_buffer.chunks_exact(std::mem::size_of::<SrcCompType>()) {
+        let item = unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const SrcCompType) };
+        dst_comp_vec.push(DstCompType::from(item));
     }
 
     // Return
@@ -56,111 +141,335 @@ fn convert_gltf_buffer_to_f32(input_buffer: &[u8], accessor: &gltf::Accessor) ->
         gltf::accessor::DataType::F32 => reinterpret_then_convert::<f32, f64>(input_buffer),
     };
 
+    // Normalized integers map their whole range onto 0..1 (unsigned) or -1..1 (signed)
+    let divisor = match accessor.data_type() {
+        _ if !accessor.normalized() => 1.0,
+        gltf::accessor::DataType::I8 => i8::MAX as f64,
+        gltf::accessor::DataType::U8 => u8::MAX as f64,
+        gltf::accessor::DataType::I16 => i16::MAX as f64,
+        gltf::accessor::DataType::U16 => u16::MAX as f64,
+        gltf::accessor::DataType::U32 | gltf::accessor::DataType::F32 => 1.0,
+    };
+
     // Then we convert that to a f32 vector - this feels cursed as heck but let's ignore that, it'll be fine!
     let mut values32 = Vec::<f32>::new();
     values32.resize(values64.len(), 0.0);
     for i in 0..values32.len() {
-        values32[i] = values64[i] as f32;
+        let mut value = values64[i] / divisor;
+        if accessor.normalized() {
+            // The lowest value of a signed type would end up just below -1
+            value = value.max(-1.0);
+        }
+        values32[i] = value as f32;
     }
 
     // Return
     values32
 }
 
+fn convert_gltf_buffer_to_u32(
+    input_buffer: &[u8],
+    accessor: &gltf::Accessor,
+    context: &str,
+) -> Result<Vec<u32>, ConvertError> {
+    // Index buffers can only be unsigned integers, and going through f32 would lose precision on big ones
+    match accessor.data_type() {
+        gltf::accessor::DataType::U8 => Ok(reinterpret_then_convert::<u8, u32>(input_buffer)),
+        gltf::accessor::DataType::U16 => Ok(reinterpret_then_convert::<u16, u32>(input_buffer)),
+        gltf::accessor::DataType::U32 => Ok(reinterpret_then_convert::<u32, u32>(input_buffer)),
+        data_type => Err(ConvertError::malformed(
+            context,
+            format!("index buffer has component type {data_type:?}"),
+        )),
+    }
+}
+
+fn get_view_slice<'a>(
+    bufferview: &gltf::buffer::View,
+    mesh_data: &'a [Data],
+    context: &str,
+) -> Result<&'a [u8], ConvertError> {
+    // Find location in buffer
+    let buffer_index = bufferview.buffer().index();
+    let buffer_offset = bufferview.offset();
+    let buffer_end = bufferview.offset() + bufferview.length();
+
+    // Find location in buffer
+    let buffer_base = &mesh_data
+        .get(buffer_index)
+        .ok_or_else(|| {
+            ConvertError::malformed(context, format!("buffer {buffer_index} does not exist"))
+        })?
+        .0;
+    buffer_base.get(buffer_offset..buffer_end).ok_or_else(|| {
+        ConvertError::malformed(
+            context,
+            format!(
+                "buffer view {} lies outside of buffer {buffer_index}",
+                bufferview.index()
+            ),
+        )
+    })
+}
+
+/// The `count` elements of `element_size` bytes at `start` in `slice`, or None if they don't fit.
+fn checked_range(slice: &[u8], start: usize, count: usize, element_size: usize) -> Option<&[u8]> {
+    let end = count.checked_mul(element_size)?.checked_add(start)?;
+    slice.get(start..end)
+}
+
+// Gather the elements of an accessor into a tightly packed buffer, so the conversion functions can read it in one go
+fn get_accessor_bytes(
+    accessor: &gltf::Accessor,
+    mesh_data: &[Data],
+    context: &str,
+) -> Result<Vec<u8>, ConvertError> {
+    let element_size = accessor.size();
+    let out_of_bounds = || {
+        ConvertError::malformed(
+            context,
+            format!(
+                "accessor {} does not fit in its buffer view",
+                accessor.index()
+            ),
+        )
+    };
+
+    // Make sure all the elements fit in the buffer view before allocating anything, so a broken count
+    // can't ask for a huge buffer. The elements can be interleaved with other data, in which case the view has a stride
+    let view = match accessor.view() {
+        Some(bufferview) => {
+            let buffer_slice = get_view_slice(&bufferview, mesh_data, context)?;
+            let stride = bufferview.stride().unwrap_or(element_size);
+            let start = accessor.offset();
+            let end = match accessor.count() {
+                0 => Some(start),
+                count => (count - 1)
+                    .checked_mul(stride)
+                    .and_then(|length| length.checked_add(start))
+                    .and_then(|end| end.checked_add(element_size)),
+            };
+            match end {
+                Some(end) if stride >= element_size && end <= buffer_slice.len() => {
+                    Some((buffer_slice, stride, start, end))
+                }
+                _ => return Err(out_of_bounds()),
+            }
+        }
+        None => None,
+    };
+
+    // Without a buffer view, the accessor starts out as all zeroes
+    let length = accessor
+        .count()
+        .checked_mul(element_size)
+        .ok_or_else(out_of_bounds)?;
+    let mut bytes = vec![0u8; length];
+    if let Some((buffer_slice, stride, start, end)) = view {
+        for (element, element_start) in bytes
+            .chunks_exact_mut(element_size)
+            .zip((start..end).step_by(stride))
+        {
+            element.copy_from_slice(&buffer_slice[element_start..element_start + element_size]);
+        }
+    }
+
+    // Sparse accessors replace some of the elements with other values
+    if let Some(sparse) = accessor.sparse() {
+        let count = to_usize(sparse.count());
+
+        // Read the indices of the elements to replace
+        let indices_view = sparse.indices().view();
+        let indices_slice = get_view_slice(&indices_view, mesh_data, context)?;
+        let index_size = match sparse.indices().index_type() {
+            IndexType::U8 => 1,
+            IndexType::U16 => 2,
+            IndexType::U32 => 4,
+        };
+        let indices_start = to_usize(sparse.indices().offset());
+        let indices_bytes = checked_range(indices_slice, indices_start, count, index_size)
+            .ok_or_else(out_of_bounds)?;
+        let indices = match sparse.indices().index_type() {
+            IndexType::U8 => reinterpret_then_convert::<u8, u32>(indices_bytes),
+            IndexType::U16 => reinterpret_then_convert::<u16, u32>(indices_bytes),
+            IndexType::U32 => reinterpret_then_convert::<u32, u32>(indices_bytes),
+        };
+
+        // The new values are always tightly packed
+        let values_view = sparse.values().view();
+        let values_slice = get_view_slice(&values_view, mesh_data, context)?;
+        let values_start = to_usize(sparse.values().offset());
+        let values_bytes = checked_range(values_slice, values_start, count, element_size)
+            .ok_or_else(out_of_bounds)?;
+
+        for (index, value) in indices.iter().zip(values_bytes.chunks_exact(element_size)) {
+            let index = *index as usize;
+            if index >= accessor.count() {
+                return Err(ConvertError::malformed(
+                    context,
+                    format!(
+                        "sparse index {index} is out of range for accessor {}",
+                        accessor.index()
+                    ),
+                ));
+            }
+            bytes[index * element_size..(index + 1) * element_size].copy_from_slice(value);
+        }
+    }
+
+    Ok(bytes)
+}
+
 fn create_vertex_array(
     primitive: &gltf::Primitive,
     mesh_data: &[Data],
     local_matrix: Mat4,
-) -> Mesh {
+    context: &str,
+) -> Result<Mesh, ConvertError> {
     let mut position_vec = Vec::<Vec3>::new();
     let mut normal_vec = Vec::<Vec3>::new();
     let mut tangent_vec = Vec::<Vec4>::new();
     let mut colour_vec = Vec::<Vec4>::new();
     let mut texcoord_vec = Vec::<Vec2>::new();
-    let mut indices = Vec::<u16>::new();
+    let mut joint_vec = Vec::<u32>::new();
+    let mut weight_vec = Vec::<f32>::new();
+    let mut indices = Vec::<u32>::new();
 
     // Loop over all the primitive attributes
     for (name, accessor) in primitive.attributes() {
-        // Get buffer view
-        let bufferview = accessor.view().unwrap();
-
         // Find location in buffer
-        let buffer_index = bufferview.buffer().index();
-        let buffer_offset = bufferview.offset();
-        let buffer_end = bufferview.offset() + bufferview.length();
-
-        // Find location in buffer
-        let buffer_base = &mesh_data[buffer_index].0;
-        let buffer_slice = buffer_base.get(buffer_offset..buffer_end).unwrap();
+        let accessor_bytes = get_accessor_bytes(&accessor, mesh_data, context)?;
 
         // Assign to the vectors
         match name.to_string().as_str() {
             "POSITION" => {
-                let values = convert_gltf_buffer_to_f32(buffer_slice, &accessor);
+                let values = convert_gltf_buffer_to_f32(&accessor_bytes, &accessor);
                 for i in (0..accessor.count() * 3).step_by(3) {
                     let slice = &values[i..i + 3];
                     position_vec.push(Vec3::from_slice(slice));
                 }
             }
             "NORMAL" => {
-                let values = convert_gltf_buffer_to_f32(buffer_slice, &accessor);
+                let values = convert_gltf_buffer_to_f32(&accessor_bytes, &accessor);
                 for i in (0..accessor.count() * 3).step_by(3) {
                     let slice = &values[i..i + 3];
                     normal_vec.push(Vec3::from_slice(slice));
                 }
             }
             "TANGENT" => {
-                let values = convert_gltf_buffer_to_f32(buffer_slice, &accessor);
+                let values = convert_gltf_buffer_to_f32(&accessor_bytes, &accessor);
                 for i in (0..accessor.count() * 4).step_by(4) {
                     let slice = &values[i..i + 4];
                     tangent_vec.push(Vec4::from_slice(slice));
                 }
             }
             "TEXCOORD_0" => {
-                let values = convert_gltf_buffer_to_f32(buffer_slice, &accessor);
+                let values = convert_gltf_buffer_to_f32(&accessor_bytes, &accessor);
                 for i in (0..accessor.count() * 2).step_by(2) {
                     let slice = &values[i..i + 2];
                     texcoord_vec.push(Vec2::from_slice(slice));
                 }
             }
+            "JOINTS_0" => {
+                joint_vec = convert_gltf_buffer_to_u32(&accessor_bytes, &accessor, context)?;
+            }
+            "WEIGHTS_0" => {
+                weight_vec = convert_gltf_buffer_to_f32(&accessor_bytes, &accessor);
+            }
             "COLOR_0" => {
-                let values = convert_gltf_buffer_to_f32(buffer_slice, &accessor);
-                for i in (0..accessor.count() * 4).step_by(4) {
-                    let slice = &values[i..i + 4];
-                    colour_vec.push(Vec4::from_slice(slice));
+                let values = convert_gltf_buffer_to_f32(&accessor_bytes, &accessor);
+                if accessor.dimensions() == gltf::accessor::Dimensions::Vec3 {
+                    for i in (0..accessor.count() * 3).step_by(3) {
+                        let slice = &values[i..i + 3];
+                        colour_vec.push(Vec3::from_slice(slice).extend(1.0));
+                    }
+                } else {
+                    for i in (0..accessor.count() * 4).step_by(4) {
+                        let slice = &values[i..i + 4];
+                        colour_vec.push(Vec4::from_slice(slice));
+                    }
                 }
             }
             _ => {}
         }
     }
 
-    // Find indices
-    {
-        // Get accessor
-        let accessor = primitive.indices().unwrap();
-
-        // Get buffer view
-        let bufferview = accessor.view().unwrap();
+    // Read the position offsets of the morph targets. Targets that only change the normals don't move anything
+    let mut morph_vec = Vec::<Vec<Vec3>>::new();
+    for target in primitive.morph_targets() {
+        let mut offsets = Vec::new();
+        if let Some(accessor) = target.positions() {
+            let accessor_bytes = get_accessor_bytes(&accessor, mesh_data, context)?;
+            let values = convert_gltf_buffer_to_f32(&accessor_bytes, &accessor);
+            offsets.extend(values.chunks_exact(3).map(Vec3::from_slice));
+        }
+        morph_vec.push(offsets);
+    }
 
+    // Find indices
+    if let Some(accessor) = primitive.indices() {
         // Find location in buffer
-        let buffer_index = bufferview.buffer().index();
-        let buffer_offset = bufferview.offset();
-        let buffer_end = bufferview.offset() + bufferview.length();
+        let accessor_bytes = get_accessor_bytes(&accessor, mesh_data, context)?;
 
-        // Find location in buffer
-        let buffer_base = &mesh_data[buffer_index].0;
-        let buffer_slice = buffer_base.get(buffer_offset..buffer_end).unwrap();
+        // Convert from raw buffer to u32 vec
+        indices = convert_gltf_buffer_to_u32(&accessor_bytes, &accessor, context)?;
+    } else {
+        // Without an index buffer, the vertices are used in order
+        indices.extend(0..position_vec.len() as u32);
+    }
 
-        // Convert from raw buffer to f32 vec - this is incredibly cursed but it'll have to do
-        let indices_f32 = convert_gltf_buffer_to_f32(buffer_slice, &accessor);
-        for index in indices_f32 {
-            indices.push(index as u16);
+    // Turn strips and fans into a plain list of triangles
+    let indices = triangle_list_indices(primitive.mode(), &indices);
+
+    // All attributes need to have the same number of elements
+    let vertex_count = position_vec.len();
+    for (name, count) in [
+        ("NORMAL", normal_vec.len()),
+        ("TANGENT", tangent_vec.len()),
+        ("TEXCOORD_0", texcoord_vec.len()),
+        ("COLOR_0", colour_vec.len()),
+        ("JOINTS_0", joint_vec.len() / 4),
+        ("WEIGHTS_0", weight_vec.len() / 4),
+    ] {
+        if count != 0 && count != vertex_count {
+            return Err(ConvertError::malformed(
+                context,
+                format!("{name} has {count} elements, but POSITION has {vertex_count}"),
+            ));
+        }
+    }
+    for (target, offsets) in morph_vec.iter().enumerate() {
+        if !offsets.is_empty() && offsets.len() != vertex_count {
+            return Err(ConvertError::malformed(
+                context,
+                format!(
+                    "morph target {target} has {} positions, but POSITION has {vertex_count}",
+                    offsets.len()
+                ),
+            ));
         }
     }
 
+    // The base color factor of the material tints every vertex
+    let base_colour = Vec4::from(
+        primitive
+            .material()
+            .pbr_metallic_roughness()
+            .base_color_factor(),
+    )
+    .xyz();
+
     // Create vertex array
-    let mut mesh_out = Mesh { verts: Vec::new() };
+    let mut mesh_out = Mesh::default();
     for index in indices {
+        // Make sure the index points to an existing vertex
+        if (index as usize) >= vertex_count {
+            return Err(ConvertError::malformed(
+                context,
+                format!("vertex index {index} is out of range"),
+            ));
+        }
+
         let mut vertex = Vertex {
             position: Vec3::new(0., 0., 0.),
             normal: Vec3::new(0., 0., 0.),
@@ -181,31 +490,143 @@ fn create_vertex_array(
         if !texcoord_vec.is_empty() {
             vertex.uv = texcoord_vec[index as usize];
         }
+        // The material's tint is applied in linear space, before the gamma correction
+        let mut linear_colour = base_colour;
         if !colour_vec.is_empty() {
-            vertex.colour.x = f32::powf(colour_vec[index as usize].x, 1.0 / 2.2);
-            if vertex.colour.x > 1.0 {
-                vertex.colour.x = 1.0
-            }
-            vertex.colour.y = f32::powf(colour_vec[index as usize].y, 1.0 / 2.2);
-            if vertex.colour.y > 1.0 {
-                vertex.colour.y = 1.0
-            }
-            vertex.colour.z = f32::powf(colour_vec[index as usize].z, 1.0 / 2.2);
-            if vertex.colour.z > 1.0 {
-                vertex.colour.z = 1.0
-            }
+            linear_colour *= colour_vec[index as usize].xyz();
         }
+        vertex.colour = linear_colour.powf(1.0 / 2.2).min(Vec3::ONE);
         mesh_out.verts.push(vertex);
+        mesh_out.morphs.push(
+            morph_vec
+                .iter()
+                .map(|offsets| match offsets.get(index as usize) {
+                    Some(offset) => local_matrix.transform_vector3(*offset),
+                    None => Vec3::ZERO,
+                })
+                .collect(),
+        );
+
+        // Rigid skinning only uses the joint with the highest weight
+        if !joint_vec.is_empty() && !weight_vec.is_empty() {
+            let first = index as usize * 4;
+            let dominant = (0..4)
+                .max_by(|a, b| weight_vec[first + a].total_cmp(&weight_vec[first + b]))
+                .unwrap_or(0);
+            mesh_out.joints.push(joint_vec[first + dominant] as usize);
+        }
     }
-    mesh_out
+    Ok(mesh_out)
+}
+
+/// Read the joints of a skin, together with their inverse bind matrices.
+fn skin_joints(
+    skin: &gltf::Skin,
+    mesh_data: &[Data],
+    context: &str,
+) -> Result<Vec<(usize, Mat4)>, ConvertError> {
+    // Without inverse bind matrices, every joint uses the identity matrix
+    let mut inverse_binds = Vec::new();
+    if let Some(accessor) = skin.inverse_bind_matrices() {
+        let accessor_bytes = get_accessor_bytes(&accessor, mesh_data, context)?;
+        let values = convert_gltf_buffer_to_f32(&accessor_bytes, &accessor);
+        inverse_binds = values.chunks_exact(16).map(Mat4::from_cols_slice).collect();
+    }
+
+    let joints: Vec<(usize, Mat4)> = skin
+        .joints()
+        .enumerate()
+        .map(|(index, joint)| {
+            let inverse_bind = inverse_binds.get(index).copied();
+            (joint.index(), inverse_bind.unwrap_or(Mat4::IDENTITY))
+        })
+        .collect();
+    if skin.inverse_bind_matrices().is_some() && inverse_binds.len() != joints.len() {
+        return Err(ConvertError::malformed(
+            context,
+            format!(
+                "the skin has {} joints, but {} inverse bind matrices",
+                joints.len(),
+                inverse_binds.len()
+            ),
+        ));
+    }
+    Ok(joints)
+}
+
+/// Move every triangle of a skinned primitive into the local space of a single bone, and tag it with that bone's node.
+/// A triangle goes to the joint that most of its vertices are weighted to, or the joint of its first vertex if they all differ.
+fn rigid_skin(
+    mesh: &mut Mesh,
+    joints: &[(usize, Mat4)],
+    context: &str,
+) -> Result<(), ConvertError> {
+    mesh.nodes.clear();
+    for (triangle, vertex_joints) in mesh.verts.chunks_mut(3).zip(mesh.joints.chunks(3)) {
+        let joint = match vertex_joints {
+            [_, b, c] if b == c => *b,
+            [a, ..] => *a,
+            [] => continue,
+        };
+        let (node, inverse_bind) = joints.get(joint).ok_or_else(|| {
+            ConvertError::malformed(context, format!("joint {joint} is not in the skin"))
+        })?;
+        for vertex in triangle {
+            vertex.position = inverse_bind.transform_point3(vertex.position);
+            vertex.normal = inverse_bind.transform_vector3(vertex.normal);
+            vertex.tangent = inverse_bind.transform_vector3(vertex.tangent);
+        }
+        mesh.nodes.push(*node);
+    }
+    mesh.skinned = vec![true; mesh.nodes.len()];
+    mesh.joints.clear();
+    Ok(())
+}
+
+// Expand the indices of a triangle primitive to a triangle list, keeping the winding order the same
+fn triangle_list_indices(mode: Mode, indices: &[u32]) -> Vec<u32> {
+    let n_triangles = match mode {
+        Mode::TriangleStrip | Mode::TriangleFan => indices.len().saturating_sub(2),
+        _ => indices.len() / 3,
+    };
+    let mut list = Vec::with_capacity(n_triangles * 3);
+    for i in 0..n_triangles {
+        match mode {
+            // Every other triangle in a strip is flipped
+            Mode::TriangleStrip if i % 2 == 0 => {
+                list.extend([indices[i], indices[i + 1], indices[i + 2]])
+            }
+            Mode::TriangleStrip => list.extend([indices[i], indices[i + 2], indices[i + 1]]),
+            Mode::TriangleFan => list.extend([indices[i + 1], indices[i + 2], indices[0]]),
+            _ => list.extend_from_slice(&indices[i * 3..i * 3 + 3]),
+        }
+    }
+    list
 }
 
 fn traverse_nodes(
     node: &gltf::Node,
-    mesh_data: &Vec<Data>,
+    mesh_data: &[Data],
     local_transform: Mat4,
-    primitives_processed: &mut HashMap<String, Mesh>,
-) {
+    filter: &NodeFilter,
+    parent_included: bool,
+    primitives_processed: &mut BTreeMap<Option<usize>, Mesh>,
+    warnings: &mut Vec<String>,
+    instances: &mut Vec<Instance>,
+    instancing: bool,
+    animated_nodes: &[usize],
+    parent_animated: bool,
+    entities: &mut Vec<Entity>,
+    bones: &mut Vec<Bone>,
+    morphs: &mut Vec<Morph>,
+) -> Result<(), ConvertError> {
+    // Skip this node and everything below it if it's filtered out
+    if filter.excludes(node) {
+        return Ok(());
+    }
+    let included = parent_included || filter.includes(node);
+    let animated = parent_animated || animated_nodes.contains(&node.index());
+
     // Convert translation in GLTF model to a Mat4.
     let node_transform = Transform {
         scale: glam::vec3(
@@ -228,20 +649,159 @@ fn traverse_nodes(
 
     let new_local_transform = local_transform * node_transform.local_matrix();
 
+    // Nodes without a mesh are markers for the game, like spawn points and triggers
+    if included && node.mesh().is_none() {
+        entities.push(Entity {
+            node: node.index(),
+            transform: new_local_transform,
+            extras: node_extras(node),
+        });
+    }
+
     // If it has a mesh, process it
-    let mesh = node.mesh();
+    let mesh = node.mesh().filter(|_| included);
     if let Some(mesh) = mesh {
+        // Skinned meshes ignore the transform of their node, and get stored per bone instead
+        let mut mesh_transform = new_local_transform;
+        let joints = match node.skin() {
+            Some(skin) => {
+                let context = format!(
+                    "node '{}', skin '{}'",
+                    node.name().unwrap_or("untitled"),
+                    skin.name().unwrap_or("untitled")
+                );
+                let joints = skin_joints(&skin, mesh_data, &context)?;
+                for (joint, inverse_bind) in &joints {
+                    if !bones.iter().any(|bone| bone.node == *joint) {
+                        bones.push(Bone {
+                            node: *joint,
+                            parent: None,
+                            bind: inverse_bind.inverse(),
+                        });
+                    }
+                }
+                mesh_transform = Mat4::IDENTITY;
+                Some(joints)
+            }
+            None => None,
+        };
+
+        // When instancing, every glTF mesh is only converted once, in local space, for the first node that uses it.
+        // Without instancing, only the meshes that get moved by an animation are kept in local space
+        let mut convert_geometry = true;
+        if joints.is_none() && (instancing || animated) {
+            let shared = instances
+                .iter()
+                .find(|other| instancing && other.mesh == mesh.index());
+            let prototype = match shared {
+                Some(other) => {
+                    convert_geometry = false;
+                    other.prototype
+                }
+                None => node.index(),
+            };
+            instances.push(Instance {
+                mesh: mesh.index(),
+                node: node.index(),
+                prototype,
+                transform: new_local_transform,
+            });
+            mesh_transform = Mat4::IDENTITY;
+        }
+
+        // Remember the morph targets of the mesh, so they can be exported for the triangles of this node
+        let n_targets = mesh
+            .primitives()
+            .map(|primitive| primitive.morph_targets().len())
+            .max()
+            .unwrap_or(0);
+        if n_targets > 0 && convert_geometry {
+            if joints.is_some() {
+                warnings.push(format!(
+                    "node '{}': morph targets on skinned meshes are not supported, so they were skipped",
+                    node.name().unwrap_or("untitled")
+                ));
+            } else {
+                let names: Vec<String> = mesh
+                    .extras()
+                    .as_ref()
+                    .and_then(|extras| {
+                        serde_json::from_str::<Map<String, Value>>(extras.get()).ok()
+                    })
+                    .and_then(|extras| {
+                        serde_json::from_value(extras.get("targetNames")?.clone()).ok()
+                    })
+                    .unwrap_or_default();
+                let mut weights = node
+                    .weights()
+                    .or(mesh.weights())
+                    .unwrap_or_default()
+                    .to_vec();
+                weights.resize(n_targets, 0.0);
+                morphs.push(Morph {
+                    node: node.index(),
+                    target_names: (0..n_targets)
+                        .map(|target| match names.get(target) {
+                            Some(name) => name.clone(),
+                            None => format!("target {target}"),
+                        })
+                        .collect(),
+                    weights,
+                });
+            }
+        }
+
         // Get mesh
-        let primitives = mesh.primitives();
+        let primitives = mesh.primitives().filter(|_| convert_geometry);
 
         for primitive in primitives {
+            let context = format!(
+                "node '{}', mesh '{}', primitive {}",
+                node.name().unwrap_or("untitled"),
+                mesh.name().unwrap_or("untitled"),
+                primitive.index()
+            );
+
+            // Points and lines can't be drawn as triangles
+            if !matches!(
+                primitive.mode(),
+                Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+            ) {
+                warnings.push(format!(
+                    "{context}: skipped, because {:?} primitives are not supported",
+                    primitive.mode()
+                ));
+                continue;
+            }
+
             let mut mesh_buffer_data =
-                create_vertex_array(&primitive, mesh_data, new_local_transform);
-            let material = String::from(primitive.material().name().unwrap_or("None"));
+                create_vertex_array(&primitive, mesh_data, mesh_transform, &context)?;
+            if joints.is_some() {
+                mesh_buffer_data.morphs.iter_mut().for_each(Vec::clear);
+            }
+            match &joints {
+                Some(joints) if mesh_buffer_data.joints.len() == mesh_buffer_data.verts.len() => {
+                    rigid_skin(&mut mesh_buffer_data, joints, &context)?;
+                }
+                _ => {
+                    if joints.is_some() {
+                        warnings.push(format!(
+                            "{context}: the node has a skin, but the primitive has no JOINTS_0 and WEIGHTS_0, so it stays in its bind pose"
+                        ));
+                    }
+                    mesh_buffer_data.joints.clear();
+                    mesh_buffer_data.nodes = vec![node.index(); mesh_buffer_data.verts.len() / 3];
+                    mesh_buffer_data.skinned = vec![false; mesh_buffer_data.nodes.len()];
+                }
+            }
+            let material = primitive.material().index();
             #[allow(clippy::map_entry)] // This was really annoying and made the code less readable
             if primitives_processed.contains_key(&material) {
                 let mesh: &mut Mesh = primitives_processed.get_mut(&material).unwrap();
                 mesh.verts.append(&mut mesh_buffer_data.verts);
+                mesh.nodes.append(&mut mesh_buffer_data.nodes);
+                mesh.skinned.append(&mut mesh_buffer_data.skinned);
+                mesh.morphs.append(&mut mesh_buffer_data.morphs);
             } else {
                 primitives_processed.insert(material, mesh_buffer_data);
             }
@@ -250,29 +810,285 @@ fn traverse_nodes(
 
     // If it has children, process those
     for child in node.children() {
-        traverse_nodes(&child, mesh_data, new_local_transform, primitives_processed);
+        traverse_nodes(
+            &child,
+            mesh_data,
+            new_local_transform,
+            filter,
+            included,
+            primitives_processed,
+            warnings,
+            instances,
+            instancing,
+            animated_nodes,
+            animated,
+            entities,
+            bones,
+            morphs,
+        )?;
     }
+
+    Ok(())
 }
 
 impl Model {
-    pub(crate) fn create_from_gltf(&mut self, path: &Path) {
+    pub fn create_from_gltf(
+        &mut self,
+        path: &Path,
+        scene_ref: Option<&SceneRef>,
+        filter: &NodeFilter,
+        instancing: bool,
+    ) -> Result<(), ConvertError> {
         // Load GLTF from file
         let gltf_file = gltf::import(path);
-        let (gltf_document, mesh_data, image_data) = gltf_file.unwrap();
+        let (gltf_document, mesh_data, image_data) =
+            gltf_file.map_err(|err| ConvertError::from_gltf(path, err))?;
+        let scene = find_scene(&gltf_document, scene_ref).map_err(|err| err.with_path(path))?;
+        self.create_from_gltf_document(
+            &gltf_document,
+            &mesh_data,
+            &image_data,
+            Some(&scene),
+            filter,
+            instancing,
+        )
+        .map_err(|err| err.with_path(path))
+    }
 
-        // Loop over each scene
-        let scene = gltf_document.default_scene();
+    pub fn create_from_gltf_slice(
+        &mut self,
+        bytes: &[u8],
+        scene_ref: Option<&SceneRef>,
+        filter: &NodeFilter,
+        instancing: bool,
+    ) -> Result<(), ConvertError> {
+        // Load GLTF from memory
+        let gltf_file = gltf::import_slice(bytes);
+        let (gltf_document, mesh_data, image_data) =
+            gltf_file.map_err(|err| ConvertError::from_gltf(Path::new(""), err))?;
+        let scene = find_scene(&gltf_document, scene_ref)?;
+        self.create_from_gltf_document(
+            &gltf_document,
+            &mesh_data,
+            &image_data,
+            Some(&scene),
+            filter,
+            instancing,
+        )
+    }
+
+    pub fn create_from_gltf_document(
+        &mut self,
+        gltf_document: &gltf::Document,
+        mesh_data: &[Data],
+        image_data: &[gltf::image::Data],
+        scene: Option<&gltf::Scene>,
+        filter: &NodeFilter,
+        instancing: bool,
+    ) -> Result<(), ConvertError> {
+        // Remember the node names, so we can refer to them later
+        self.node_names = gltf_document
+            .nodes()
+            .map(|node| match node.name() {
+                Some(name) => String::from(name),
+                None => format!("node {}", node.index()),
+            })
+            .collect();
+        self.node_parents = vec![None; self.node_names.len()];
+        for node in gltf_document.nodes() {
+            for child in node.children() {
+                self.node_parents[child.index()] = Some(node.index());
+            }
+        }
+
+        // Find the nodes that get moved by an animation, so their meshes can be kept in local space
+        let animated_nodes: Vec<usize> = gltf_document
+            .animations()
+            .flat_map(|animation| animation.channels())
+            .filter(|channel| {
+                channel.target().property() != gltf::animation::Property::MorphTargetWeights
+            })
+            .map(|channel| channel.target().node().index())
+            .collect();
+
+        // Convert the nodes of the scene, if there is one
         if let Some(scene) = scene {
-            // For each scene, get the nodes
             for node in scene.nodes() {
-                traverse_nodes(&node, &mesh_data, Mat4::IDENTITY, &mut self.meshes);
+                traverse_nodes(
+                    &node,
+                    mesh_data,
+                    Mat4::IDENTITY,
+                    filter,
+                    false,
+                    &mut self.meshes,
+                    &mut self.warnings,
+                    &mut self.instances,
+                    instancing,
+                    &animated_nodes,
+                    false,
+                    &mut self.entities,
+                    &mut self.bones,
+                    &mut self.morphs,
+                )?;
+            }
+        }
+
+        // Read the animations, keeping only the channels that move nodes of this scene
+        self.node_transforms = gltf_document
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                Transform {
+                    translation: Vec3::from(translation),
+                    rotation: Quat::from_array(rotation),
+                    scale: Vec3::from(scale),
+                }
+            })
+            .collect();
+        if let Some(scene) = scene {
+            let scene_roots: Vec<usize> = scene.nodes().map(|node| node.index()).collect();
+            let in_scene = |mut node: usize| {
+                while let Some(parent) = self.node_parents[node] {
+                    node = parent;
+                }
+                scene_roots.contains(&node)
+            };
+            for animation in gltf_document.animations() {
+                let name = match animation.name() {
+                    Some(name) => String::from(name),
+                    None => format!("animation {}", animation.index()),
+                };
+                let context = format!("animation '{name}'");
+                let mut channels = Vec::new();
+                for (channel_index, channel) in animation.channels().enumerate() {
+                    let node = channel.target().node().index();
+                    let property = match channel.target().property() {
+                        gltf::animation::Property::Translation => Property::Translation,
+                        gltf::animation::Property::Rotation => Property::Rotation,
+                        gltf::animation::Property::Scale => Property::Scale,
+                        gltf::animation::Property::MorphTargetWeights => {
+                            match self.morphs.iter().find(|morph| morph.node == node) {
+                                Some(morph) => Property::Weights(morph.target_names.len()),
+                                None => continue,
+                            }
+                        }
+                    };
+                    if !in_scene(node) {
+                        continue;
+                    }
+
+                    // Read the keyframes
+                    let sampler = channel.sampler();
+                    let (input, output) = (sampler.input(), sampler.output());
+                    let times = convert_gltf_buffer_to_f32(
+                        &get_accessor_bytes(&input, mesh_data, &context)?,
+                        &input,
+                    );
+                    let values = convert_gltf_buffer_to_f32(
+                        &get_accessor_bytes(&output, mesh_data, &context)?,
+                        &output,
+                    );
+                    let values_per_key = match sampler.interpolation() {
+                        Interpolation::CubicSpline => 3 * property.components(),
+                        _ => property.components(),
+                    };
+                    if values.len() != times.len() * values_per_key {
+                        return Err(ConvertError::malformed(
+                            &context,
+                            format!(
+                                "channel {} has {} keyframes, but {} values",
+                                channel_index,
+                                times.len(),
+                                values.len() / values_per_key.max(1)
+                            ),
+                        ));
+                    }
+                    if times.is_empty() {
+                        continue;
+                    }
+                    channels.push(Channel {
+                        node,
+                        property,
+                        interpolation: sampler.interpolation(),
+                        times,
+                        values,
+                    });
+                }
+                if !channels.is_empty() {
+                    self.animations.push(Animation { name, channels });
+                }
             }
         }
 
+        // Skins ignore the include patterns, because their joints are usually outside of the included nodes. Excluded
+        // joints are left out though, together with the triangles that are skinned to them
+        let excluded = |mut node: usize| loop {
+            if gltf_document
+                .nodes()
+                .nth(node)
+                .is_some_and(|node| filter.excludes(&node))
+            {
+                return true;
+            }
+            match self.node_parents[node] {
+                Some(parent) => node = parent,
+                None => return false,
+            }
+        };
+        let excluded_joints: Vec<usize> = self
+            .bones
+            .iter()
+            .map(|bone| bone.node)
+            .filter(|node| excluded(*node))
+            .collect();
+        if !excluded_joints.is_empty() {
+            self.bones
+                .retain(|bone| !excluded_joints.contains(&bone.node));
+            for mesh in self.meshes.values_mut() {
+                mesh.retain_triangles(|node, skinned| {
+                    !(skinned && excluded_joints.contains(&node))
+                });
+            }
+        }
+
+        // Bones are mesh-less nodes too, but they belong to the skeleton, not the entities
+        let bones = &self.bones;
+        self.entities
+            .retain(|entity| !bones.iter().any(|bone| bone.node == entity.node));
+
+        // Sort the bones so parents always come first, then link each of them to its parent bone
+        let depth = |mut node: usize| {
+            let mut depth = 0;
+            while let Some(parent) = self.node_parents[node] {
+                node = parent;
+                depth += 1;
+            }
+            depth
+        };
+        self.bones.sort_by_key(|bone| (depth(bone.node), bone.node));
+        for index in 0..self.bones.len() {
+            let mut node = self.bones[index].node;
+            self.bones[index].parent = loop {
+                let Some(parent) = self.node_parents[node] else {
+                    break None;
+                };
+                if let Some(parent) = self.bones.iter().position(|bone| bone.node == parent) {
+                    break Some(parent);
+                }
+                node = parent;
+            };
+        }
+
         // Get all the textures from the GLTF
         for material in gltf_document.materials() {
             let _new_material; // this is unused for now
 
+            // The name is only used to tell materials apart when reading the output
+            let name = match material.name() {
+                Some(name) => String::from(name),
+                None => format!("material {}", material.index().unwrap_or_default()),
+            };
+
             // Get the base texture info
             let gltf_tex_info = material.pbr_metallic_roughness().base_color_texture();
 
@@ -283,10 +1099,13 @@ impl Model {
                 let gltf_tex = gltf_tex_info_unwrapped.texture().source().index();
 
                 // Get image data
-                let image = &image_data[gltf_tex];
+                let context = format!("material '{name}'");
+                let image = image_data.get(gltf_tex).ok_or_else(|| {
+                    ConvertError::malformed(&context, format!("image {gltf_tex} does not exist"))
+                })?;
 
                 // Load the texture from that image data
-                tex = Texture::load_texture_from_gltf_image(image);
+                tex = Texture::load_texture_from_gltf_image(image, &context)?;
                 // Generate mipmaps
                 //tex.generate_mipmaps();
 
@@ -341,49 +1160,39 @@ impl Model {
                 };
 
                 _new_material = Material {
+                    name,
                     texture: tex,
                     sampler: new_sampler,
                 };
-                println!(
-                    "Found texture '{}' ({}x{})",
-                    material.name().unwrap_or(""),
-                    _new_material.texture.width,
-                    _new_material.texture.height
-                )
             }
             // If there is no base texture, generate a white one
             else {
-                _new_material = Material {
-                    texture: Texture {
-                        width: 64,
-                        height: 64,
-                        depth: 1,
-                        data: vec![0xFFFFFFFFu32; 64 * 64],
-                        mipmap_offsets: vec![0usize; 1],
-                        avg_color: 0xFFFFFFFFu32,
-                    },
-                    sampler: Sampler {
-                        filter_mode_mag: FilterMode::Point,
-                        filter_mode_min: FilterMode::Point,
-                        filter_mode_mipmap: FilterMode::Point,
-                        wrap_mode_s: WrapMode::Clamp,
-                        wrap_mode_t: WrapMode::Clamp,
-                        mipmap_enabled: false,
-                    },
-                };
+                _new_material = Material::white(name);
             }
 
-            self.materials.insert(
-                String::from(material.name().unwrap_or("untitled")),
-                _new_material,
-            );
+            self.materials.insert(material.index(), _new_material);
         }
+
+        // Primitives without a material use the default material, which is plain white
+        self.materials
+            .insert(None, Material::white(String::from("default")));
+
+        Ok(())
     }
 
-    pub(crate) fn new() -> Model {
+    pub fn new() -> Model {
         Model {
-            meshes: HashMap::new(),
-            materials: HashMap::new(),
+            meshes: BTreeMap::new(),
+            materials: BTreeMap::new(),
+            node_names: Vec::new(),
+            node_parents: Vec::new(),
+            warnings: Vec::new(),
+            instances: Vec::new(),
+            entities: Vec::new(),
+            bones: Vec::new(),
+            node_transforms: Vec::new(),
+            animations: Vec::new(),
+            morphs: Vec::new(),
         }
     }
 }
@@ -0,0 +1,171 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use glam::Vec3;
+
+/// The deepest an octree can go. Every split is a level of recursion, so this keeps a big `max_depth`
+/// from running out of stack on triangles that can't be separated.
+pub const MAX_OCTREE_DEPTH: u32 = 32;
+
+/// How the triangles of a model are split up into submeshes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Partition {
+    /// Put every triangle in a single submesh.
+    None,
+    /// Split the model using a uniform grid, with the given cell size in glTF units.
+    Grid(Vec3),
+    /// Split the model's bounding box into octants until a cell has at most `max_triangles`
+    /// triangles, or `max_depth` splits have been made.
+    Octree {
+        max_triangles: usize,
+        max_depth: u32,
+    },
+    /// Split the triangles in half along the longest axis until a cell has at most
+    /// `max_triangles` triangles. Every cell ends up with about the same number of triangles.
+    KdTree { max_triangles: usize },
+}
+
+/// The different partition strategies, without their settings. Used to pick one by name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PartitionKind {
+    None,
+    Grid,
+    Octree,
+    KdTree,
+}
+
+impl FromStr for PartitionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(PartitionKind::None),
+            "grid" => Ok(PartitionKind::Grid),
+            "octree" => Ok(PartitionKind::Octree),
+            "kd" => Ok(PartitionKind::KdTree),
+            _ => Err(format!(
+                "unknown partition strategy '{s}', expected 'none', 'grid', 'octree' or 'kd'"
+            )),
+        }
+    }
+}
+
+/// Split a set of triangles into groups, based on the center of each triangle.
+/// Returns the indices into `centroids` for each group. Empty groups are never returned.
+/// The groups and the triangles in them always come out in the same order for the same input.
+pub fn partition_triangles(centroids: &[Vec3], partition: &Partition) -> Vec<Vec<usize>> {
+    if centroids.is_empty() {
+        return Vec::new();
+    }
+
+    let all_triangles: Vec<usize> = (0..centroids.len()).collect();
+    match partition {
+        Partition::None => vec![all_triangles],
+        Partition::Grid(cell_size) => split_grid(centroids, *cell_size),
+        Partition::Octree {
+            max_triangles,
+            max_depth,
+        } => {
+            let mut groups = Vec::new();
+            split_octree(
+                centroids,
+                all_triangles,
+                (*max_triangles).max(1),
+                (*max_depth).min(MAX_OCTREE_DEPTH),
+                &mut groups,
+            );
+            groups
+        }
+        Partition::KdTree { max_triangles } => {
+            let mut groups = Vec::new();
+            split_kd(
+                centroids,
+                all_triangles,
+                (*max_triangles).max(1),
+                &mut groups,
+            );
+            groups
+        }
+    }
+}
+
+fn split_grid(centroids: &[Vec3], cell_size: Vec3) -> Vec<Vec<usize>> {
+    // The cells are ordered by X, then Y, then Z, so the output is the same every time
+    let mut grid: BTreeMap<(i32, i32, i32), Vec<usize>> = BTreeMap::new();
+    for (index, centroid) in centroids.iter().enumerate() {
+        // Find which grid cell this triangle belongs to
+        let cell = (*centroid / cell_size).round();
+        grid.entry((cell.x as i32, cell.y as i32, cell.z as i32))
+            .or_default()
+            .push(index);
+    }
+    grid.into_values().collect()
+}
+
+fn bounds(centroids: &[Vec3], triangles: &[usize]) -> (Vec3, Vec3) {
+    let mut min = Vec3::splat(f32::MAX);
+    let mut max = Vec3::splat(f32::MIN);
+    for &index in triangles {
+        min = min.min(centroids[index]);
+        max = max.max(centroids[index]);
+    }
+    (min, max)
+}
+
+fn split_octree(
+    centroids: &[Vec3],
+    triangles: Vec<usize>,
+    max_triangles: usize,
+    depth_left: u32,
+    groups: &mut Vec<Vec<usize>>,
+) {
+    if triangles.len() <= max_triangles || depth_left == 0 {
+        groups.push(triangles);
+        return;
+    }
+
+    // Sort the triangles into the 8 octants around the center of this cell
+    let (min, max) = bounds(centroids, &triangles);
+    let center = (min + max) / 2.0;
+    let mut octants: [Vec<usize>; 8] = Default::default();
+    for index in triangles {
+        let centroid = centroids[index];
+        let octant = (centroid.x > center.x) as usize
+            | ((centroid.y > center.y) as usize) << 1
+            | ((centroid.z > center.z) as usize) << 2;
+        octants[octant].push(index);
+    }
+
+    for octant in octants {
+        if !octant.is_empty() {
+            split_octree(centroids, octant, max_triangles, depth_left - 1, groups);
+        }
+    }
+}
+
+fn split_kd(
+    centroids: &[Vec3],
+    mut triangles: Vec<usize>,
+    max_triangles: usize,
+    groups: &mut Vec<Vec<usize>>,
+) {
+    if triangles.len() <= max_triangles {
+        groups.push(triangles);
+        return;
+    }
+
+    // Split along the longest axis of this cell, at the median triangle
+    let (min, max) = bounds(centroids, &triangles);
+    let extent = max - min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+    triangles.sort_by(|a, b| centroids[*a][axis].total_cmp(&centroids[*b][axis]));
+    let upper_half = triangles.split_off(triangles.len() / 2);
+
+    split_kd(centroids, triangles, max_triangles, groups);
+    split_kd(centroids, upper_half, max_triangles, groups);
+}
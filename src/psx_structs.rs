@@ -1,6 +1,53 @@
-use std::{fs::File, io::Write, path::Path};
+use std::{collections::BTreeMap, path::Path};
 
-use crate::{helpers::validate, structs::Vertex};
+use glam::Vec3;
+
+use crate::{error::ConvertError, structs::Vertex};
+
+/// Version of the .msh format written by `ModelPSX::save`. Bumped every time the layout changes.
+pub const MSH_VERSION: u32 = 5;
+
+/// Size of the .msh header in bytes. All offsets in the file are relative to the end of it.
+pub const MSH_HEADER_SIZE: usize = 32;
+
+/// Version of the .ent format written by `EntityCollectionPSX::save`.
+pub const ENT_VERSION: u32 = 1;
+
+/// Size of the .ent header in bytes. All offsets in the file are relative to the end of it.
+pub const ENT_HEADER_SIZE: usize = 28;
+
+/// Version of the .skl format written by `SkeletonPSX::save`.
+pub const SKL_VERSION: u32 = 1;
+
+/// Size of the .skl header in bytes. All offsets in the file are relative to the end of it.
+pub const SKL_HEADER_SIZE: usize = 20;
+
+/// Version of the .anm format written by `AnimationCollectionPSX::save`.
+pub const ANM_VERSION: u32 = 1;
+
+/// Size of the .anm header in bytes. All offsets in the file are relative to the end of it.
+pub const ANM_HEADER_SIZE: usize = 32;
+
+/// Version of the .vta format written by `VertexAnimationPSX::save`.
+pub const VTA_VERSION: u32 = 1;
+
+/// Size of the .vta header in bytes. All offsets in the file are relative to the end of it.
+pub const VTA_HEADER_SIZE: usize = 28;
+
+/// Flag of a morph sequence in a .vta file that holds a resampled animation clip, instead of a single morph target.
+pub const SEQUENCE_ANIMATED: u32 = 1;
+
+/// Value of a bone's parent or submesh index in the file when it doesn't have one.
+pub const NO_INDEX: u32 = 0xFFFFFFFF;
+
+/// Value of a table offset in the header when that table is not in the file.
+pub const NO_TABLE: u32 = 0xFFFFFFFF;
+
+/// Type tags of the entity properties in a .ent file.
+pub const PROPERTY_BOOL: u32 = 0;
+pub const PROPERTY_INT: u32 = 1;
+pub const PROPERTY_FIXED: u32 = 2;
+pub const PROPERTY_STRING: u32 = 3;
 
 #[derive(Clone, Copy)]
 pub struct VertexPSX {
@@ -15,26 +62,214 @@ pub struct VertexPSX {
     pub texture_id: u8,
 }
 
+#[derive(Default)]
 pub struct MeshPSX {
     pub verts: Vec<VertexPSX>,
+    /// Fixed-point position the vertex positions are relative to.
+    pub origin: [i32; 3],
+    /// Name of the glTF node this submesh was made from, if it was kept as its own object.
+    /// Named submeshes end up in the name table.
+    pub name: Option<String>,
 }
 
 pub struct ModelPSX {
     pub meshes: Vec<MeshPSX>,
+    /// Number of fixed-point units per glTF unit the vertices were converted with.
+    pub scale: f32,
+    /// Placements of the submeshes in local space: every mesh with instancing, otherwise the animated ones.
+    pub instances: Vec<InstancePSX>,
+}
+
+/// A fixed-point transform: a vertex ends up at `rotation * (scale * vertex) + position`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransformPSX {
+    /// Fixed-point position, in the same units as the vertices.
+    pub position: [i32; 3],
+    /// Rotation matrix in 4.12 fixed point, row by row.
+    pub rotation: [i16; 9],
+    /// Scale along each axis in 4.12 fixed point.
+    pub scale: [i16; 3],
+}
+
+/// A placement of a submesh in the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InstancePSX {
+    pub submesh_index: u32,
+    pub transform: TransformPSX,
+}
+
+/// A value in the extras of an entity.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i32),
+    /// A number with a fractional part, in 20.12 fixed point.
+    Fixed(i32),
+    String(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityPropertyPSX {
+    pub key: String,
+    pub value: PropertyValue,
+}
+
+/// A glTF node without a mesh, like a spawn point or a trigger.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityPSX {
+    pub name: String,
+    pub transform: TransformPSX,
+    pub properties: Vec<EntityPropertyPSX>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EntityCollectionPSX {
+    pub entities: Vec<EntityPSX>,
+}
+
+/// A bone of a skinned model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BonePSX {
+    pub name: String,
+    /// Index of the parent bone. Parents always come before their children.
+    pub parent: Option<u32>,
+    /// Index of the submesh with the triangles of this bone, in the bone's local space.
+    pub submesh_index: Option<u32>,
+    /// Bind pose transform, relative to the parent bone.
+    pub transform: TransformPSX,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SkeletonPSX {
+    pub bones: Vec<BonePSX>,
+}
+
+/// The animation of a single node in a clip.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackPSX {
+    /// Name of the animated node.
+    pub name: String,
+    /// Index of the bone in the .skl file, if the node is a bone.
+    pub bone_index: Option<u32>,
+    /// Index of the submesh this track moves: the submesh of the bone, or the mesh of the node in local space.
+    pub submesh_index: Option<u32>,
+    /// The transform of the node for every frame of the clip. Bones are relative to their parent bone,
+    /// everything else is in model space.
+    pub frames: Vec<TransformPSX>,
+}
+
+/// An animation clip, resampled to a fixed frame rate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClipPSX {
+    pub name: String,
+    pub n_frames: u32,
+    pub tracks: Vec<TrackPSX>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationCollectionPSX {
+    pub clips: Vec<ClipPSX>,
+    /// Number of frames per second every clip was sampled at.
+    pub frame_rate: f32,
+}
+
+/// Vertex position offsets for one submesh, either a single morph target or an animation clip that blends them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MorphSequencePSX {
+    /// Name of the morph target, or of the animation clip.
+    pub name: String,
+    /// The submesh in the .msh file the offsets apply to.
+    pub submesh_index: u32,
+    /// True if this is a resampled animation clip, false if it's a single morph target.
+    pub animated: bool,
+    /// For every frame, the offset of every vertex of the submesh in fixed-point units, in the same order as the vertices.
+    pub frames: Vec<Vec<[i16; 3]>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexAnimationPSX {
+    pub sequences: Vec<MorphSequencePSX>,
+    /// Number of frames per second the animated sequences were sampled at.
+    pub frame_rate: f32,
+}
+
+/// A morph sequence as it's stored in a .vta file.
+#[derive(Clone, Copy)]
+pub struct SequenceBinary {
+    pub name_hash: u32,
+    pub offset_name: u32,
+    pub submesh_index: u32,
+    pub n_vertices: u32,
+    pub n_frames: u32,
+    pub flags: u32,
+    pub offset_deltas: u32,
+}
+
+/// A clip as it's stored in a .anm file.
+#[derive(Clone, Copy)]
+pub struct ClipBinary {
+    pub name_hash: u32,
+    pub offset_name: u32,
+    pub n_frames: u32,
+    pub first_track: u32,
+    pub n_tracks: u32,
+}
+
+/// A track as it's stored in a .anm file.
+#[derive(Clone, Copy)]
+pub struct TrackBinary {
+    pub name_hash: u32,
+    pub offset_name: u32,
+    pub bone_index: u32,
+    pub submesh_index: u32,
+    pub offset_frames: u32,
+}
+
+/// A bone as it's stored in a .skl file.
+#[derive(Clone, Copy)]
+pub struct BoneBinary {
+    pub name_hash: u32,
+    pub offset_name: u32,
+    pub parent: u32,
+    pub submesh_index: u32,
+    pub transform: TransformPSX,
+}
+
+/// An entity as it's stored in a .ent file.
+#[derive(Clone, Copy)]
+pub struct EntityBinary {
+    pub name_hash: u32,
+    pub offset_name: u32,
+    pub transform: TransformPSX,
+    pub first_property: u32,
+    pub n_properties: u32,
+}
+
+/// A property as it's stored in a .ent file.
+#[derive(Clone, Copy)]
+pub struct PropertyBinary {
+    pub key_hash: u32,
+    pub offset_key: u32,
+    pub kind: u32,
+    pub value: u32,
 }
 
 #[derive(Clone, Copy)]
 pub struct MeshDesc {
-    pub vertex_start: u16,
-    pub n_vertices: u16,
+    pub vertex_start: u32,
+    pub n_vertices: u32,
     pub x_min: i16,
     pub x_max: i16,
     pub y_min: i16,
     pub y_max: i16,
     pub z_min: i16,
     pub z_max: i16,
+    pub origin_x: i32,
+    pub origin_y: i32,
+    pub origin_z: i32,
 }
 
+#[derive(Default)]
 pub struct TextureCollectionPSX {
     pub texture_cells: Vec<TextureCellPSX>,
     pub texture_names: Vec<String>,
@@ -59,11 +294,12 @@ pub struct TextureCellBinary {
 }
 
 impl VertexPSX {
-    pub fn from(vertex: &Vertex, texture_id: u8) -> VertexPSX {
+    /// Convert a vertex. `position` is the already scaled fixed-point position, which gets clamped to 16 bits.
+    pub fn from(vertex: &Vertex, position: Vec3, texture_id: u8) -> VertexPSX {
         VertexPSX {
-            pos_x: (-1024.0 * vertex.position.x).clamp(-32768.0, 32767.0) as i16,
-            pos_y: (-1024.0 * vertex.position.y).clamp(-32768.0, 32767.0) as i16,
-            pos_z: (1024.0 * vertex.position.z).clamp(-32768.0, 32767.0) as i16,
+            pos_x: position.x.clamp(-32768.0, 32767.0) as i16,
+            pos_y: position.y.clamp(-32768.0, 32767.0) as i16,
+            pos_z: position.z.clamp(-32768.0, 32767.0) as i16,
             color_r: (255.0 * vertex.colour.x).clamp(0.0, 255.0) as u8,
             color_g: (255.0 * vertex.colour.y).clamp(0.0, 255.0) as u8,
             color_b: (255.0 * vertex.colour.z).clamp(0.0, 255.0) as u8,
@@ -90,16 +326,35 @@ impl VertexPSX {
 
 impl MeshPSX {
     pub fn new() -> MeshPSX {
-        MeshPSX { verts: Vec::new() }
+        MeshPSX {
+            verts: Vec::new(),
+            origin: [0; 3],
+            name: None,
+        }
+    }
+}
+
+impl Default for ModelPSX {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl ModelPSX {
     pub fn new() -> ModelPSX {
-        ModelPSX { meshes: Vec::new() }
+        ModelPSX {
+            meshes: Vec::new(),
+            scale: 1024.0,
+            instances: Vec::new(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConvertError> {
+        let bytes = self.to_bytes().map_err(|err| err.with_path(path))?;
+        std::fs::write(path, bytes).map_err(|err| ConvertError::io(path, err))
     }
 
-    pub fn save(&self, path: &Path) -> std::io::Result<usize> {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ConvertError> {
         // Create binary array of data
         let mut raw_vertex_data = Vec::<VertexPSX>::new();
         let mut mesh_descs = Vec::<MeshDesc>::new();
@@ -123,67 +378,652 @@ impl ModelPSX {
             }
 
             mesh_descs.push(MeshDesc {
-                vertex_start: raw_vertex_data.len() as u16,
-                n_vertices: mesh.verts.len() as u16,
+                vertex_start: raw_vertex_data.len() as u32,
+                n_vertices: mesh.verts.len() as u32,
                 x_min,
                 x_max,
                 y_min,
                 y_max,
                 z_min,
                 z_max,
+                origin_x: mesh.origin[0],
+                origin_y: mesh.origin[1],
+                origin_z: mesh.origin[2],
             });
             for vertex in &mesh.verts {
                 raw_vertex_data.push(*vertex);
             }
         }
 
-        // Open output file
-        let mut file = File::create(path)?;
+        // Create output buffer
+        let mut file = Vec::<u8>::new();
 
         // Write file magic
-        validate(file.write("FMSH".as_bytes()));
+        file.extend_from_slice("FMSH".as_bytes());
+
+        // Write format version
+        file.extend_from_slice(&MSH_VERSION.to_le_bytes());
 
         // Write number of submeshes
-        validate(file.write(&(self.meshes.len() as u32).to_le_bytes()));
+        file.extend_from_slice(&(self.meshes.len() as u32).to_le_bytes());
 
         // The offset into the MeshDesc array is zero here
         // Let's just define it to always be this way
-        validate(file.write(&(0u32).to_le_bytes()));
+        file.extend_from_slice(&(0u32).to_le_bytes());
 
         // The vertex data is stored right after the MeshDesc array, but it's aligned to 4 bytes so the PS1 doesn't crap all over itself trying to load it
-        let vertex_data_offset = (mesh_descs.len() * 16 + 0x03) & !0x03;
-        let delta_offset = vertex_data_offset - mesh_descs.len() * 16;
+        let vertex_data_offset = (mesh_descs.len() * MeshDesc::SIZE + 0x03) & !0x03;
+        let delta_offset = vertex_data_offset - mesh_descs.len() * MeshDesc::SIZE;
 
         // Write the offset to the vertex data
-        validate(file.write(&(vertex_data_offset as u32).to_le_bytes()));
+        file.extend_from_slice(&(vertex_data_offset as u32).to_le_bytes());
+
+        // Write the scale as 16.16 fixed point, so the game knows how big a unit is
+        let fixed_point_scale = to_fixed_point_16_16(self.scale, "model", "scale")?;
+        file.extend_from_slice(&fixed_point_scale.to_le_bytes());
+
+        // Placeholders for the offsets to the name and instance tables, which are filled in once we know where they end up
+        file.extend_from_slice(&NO_TABLE.to_le_bytes());
+        file.extend_from_slice(&NO_TABLE.to_le_bytes());
 
         for value in mesh_descs {
-            validate(file.write(&value.vertex_start.to_le_bytes()));
-            validate(file.write(&value.n_vertices.to_le_bytes()));
-            validate(file.write(&value.x_min.to_le_bytes()));
-            validate(file.write(&value.x_max.to_le_bytes()));
-            validate(file.write(&value.y_min.to_le_bytes()));
-            validate(file.write(&value.y_max.to_le_bytes()));
-            validate(file.write(&value.z_min.to_le_bytes()));
-            validate(file.write(&value.z_max.to_le_bytes()));
+            file.extend_from_slice(&value.vertex_start.to_le_bytes());
+            file.extend_from_slice(&value.n_vertices.to_le_bytes());
+            file.extend_from_slice(&value.x_min.to_le_bytes());
+            file.extend_from_slice(&value.x_max.to_le_bytes());
+            file.extend_from_slice(&value.y_min.to_le_bytes());
+            file.extend_from_slice(&value.y_max.to_le_bytes());
+            file.extend_from_slice(&value.z_min.to_le_bytes());
+            file.extend_from_slice(&value.z_max.to_le_bytes());
+            file.extend_from_slice(&value.origin_x.to_le_bytes());
+            file.extend_from_slice(&value.origin_y.to_le_bytes());
+            file.extend_from_slice(&value.origin_z.to_le_bytes());
         }
 
         for _ in 0..delta_offset {
-            validate(file.write(&[0x69]));
+            file.extend_from_slice(&[0x69]);
         }
 
         for vertex in raw_vertex_data {
-            validate(file.write(&vertex.get_bytes()));
+            file.extend_from_slice(&vertex.get_bytes());
+        }
+
+        // Write the name table after the vertex data, if there are any named submeshes
+        let mut names: Vec<(u32, usize, &str)> = self
+            .meshes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, mesh)| {
+                let name = mesh.name.as_deref()?;
+                Some((name_hash(name), index, name))
+            })
+            .collect();
+        if !names.is_empty() {
+            // Sort by hash, so the game can do a binary search
+            names.sort();
+            while !file.len().is_multiple_of(4) {
+                file.push(0x69);
+            }
+            let offset_name_table = file.len() - MSH_HEADER_SIZE;
+            file[24..28].copy_from_slice(&(offset_name_table as u32).to_le_bytes());
+            file.extend_from_slice(&(names.len() as u32).to_le_bytes());
+
+            // The strings go right after the entries
+            let mut offset_name = offset_name_table + 4 + names.len() * 12;
+            for (hash, index, name) in &names {
+                file.extend_from_slice(&hash.to_le_bytes());
+                file.extend_from_slice(&(*index as u32).to_le_bytes());
+                file.extend_from_slice(&(offset_name as u32).to_le_bytes());
+                offset_name += name.len() + 1;
+            }
+            for (_, _, name) in &names {
+                file.extend_from_slice(name.as_bytes());
+                file.push(0);
+            }
+        }
+
+        // Write the instance table at the end, if any submesh is in local space
+        if !self.instances.is_empty() {
+            while !file.len().is_multiple_of(4) {
+                file.push(0x69);
+            }
+            let offset_instance_table = file.len() - MSH_HEADER_SIZE;
+            file[28..32].copy_from_slice(&(offset_instance_table as u32).to_le_bytes());
+            file.extend_from_slice(&(self.instances.len() as u32).to_le_bytes());
+            for instance in &self.instances {
+                file.extend_from_slice(&instance.to_bytes());
+            }
+        }
+
+        Ok(file)
+    }
+}
+
+impl TransformPSX {
+    /// Size of a TransformPSX in the file, in bytes.
+    pub const SIZE: usize = 36;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SIZE);
+        for value in self.position {
+            bytes.extend(value.to_le_bytes());
+        }
+        for value in self.rotation {
+            bytes.extend(value.to_le_bytes());
+        }
+        for value in self.scale {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(buffer: &[u8]) -> Self {
+        TransformPSX {
+            position: [0, 4, 8].map(|offset| u32_at(buffer, offset) as i32),
+            rotation: std::array::from_fn(|i| i16_at(buffer, 12 + i * 2)),
+            scale: std::array::from_fn(|i| i16_at(buffer, 30 + i * 2)),
+        }
+    }
+}
+
+impl InstancePSX {
+    /// Size of an InstancePSX in the file, in bytes.
+    pub const SIZE: usize = 4 + TransformPSX::SIZE;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SIZE);
+        bytes.extend(self.submesh_index.to_le_bytes());
+        bytes.extend(self.transform.to_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(buffer: &[u8]) -> Self {
+        InstancePSX {
+            submesh_index: u32_at(buffer, 0),
+            transform: TransformPSX::from_bytes(&buffer[4..]),
+        }
+    }
+}
+
+impl PropertyValue {
+    /// The type tag of this value in a .ent file.
+    pub fn kind(&self) -> u32 {
+        match self {
+            PropertyValue::Bool(_) => PROPERTY_BOOL,
+            PropertyValue::Int(_) => PROPERTY_INT,
+            PropertyValue::Fixed(_) => PROPERTY_FIXED,
+            PropertyValue::String(_) => PROPERTY_STRING,
+        }
+    }
+}
+
+impl EntityCollectionPSX {
+    pub fn new() -> Self {
+        EntityCollectionPSX {
+            entities: Vec::new(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConvertError> {
+        let bytes = self.to_bytes().map_err(|err| err.with_path(path))?;
+        std::fs::write(path, bytes).map_err(|err| ConvertError::io(path, err))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ConvertError> {
+        let n_properties: usize = self
+            .entities
+            .iter()
+            .map(|entity| entity.properties.len())
+            .sum();
+        let offset_properties = self.entities.len() * EntityBinary::SIZE;
+        let offset_strings = offset_properties + n_properties * PropertyBinary::SIZE;
+
+        // Names, keys and string values all go in one block of null-terminated strings, and
+        // strings that show up more than once are only stored once
+        let mut strings = Vec::<u8>::new();
+        let mut string_offsets = BTreeMap::<String, u32>::new();
+        let mut offset_of = |string: &str, strings: &mut Vec<u8>| -> u32 {
+            *string_offsets.entry(string.to_string()).or_insert_with(|| {
+                let offset = (offset_strings + strings.len()) as u32;
+                strings.extend_from_slice(string.as_bytes());
+                strings.push(0);
+                offset
+            })
+        };
+
+        let mut bin_entities = Vec::<u8>::new();
+        let mut bin_properties = Vec::<u8>::new();
+        let mut first_property = 0u32;
+        for entity in &self.entities {
+            bin_entities.extend_from_slice(&name_hash(&entity.name).to_le_bytes());
+            bin_entities.extend_from_slice(&offset_of(&entity.name, &mut strings).to_le_bytes());
+            bin_entities.extend(entity.transform.to_bytes());
+            bin_entities.extend_from_slice(&first_property.to_le_bytes());
+            bin_entities.extend_from_slice(&(entity.properties.len() as u32).to_le_bytes());
+            first_property += entity.properties.len() as u32;
+
+            for property in &entity.properties {
+                let value = match &property.value {
+                    PropertyValue::Bool(value) => *value as u32,
+                    PropertyValue::Int(value) | PropertyValue::Fixed(value) => *value as u32,
+                    PropertyValue::String(value) => offset_of(value, &mut strings),
+                };
+                bin_properties.extend_from_slice(&name_hash(&property.key).to_le_bytes());
+                bin_properties
+                    .extend_from_slice(&offset_of(&property.key, &mut strings).to_le_bytes());
+                bin_properties.extend_from_slice(&property.value.kind().to_le_bytes());
+                bin_properties.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        // Create output buffer
+        let mut file = Vec::<u8>::new();
+        file.extend_from_slice("FENT".as_bytes());
+        file.extend_from_slice(&ENT_VERSION.to_le_bytes());
+        file.extend_from_slice(&(self.entities.len() as u32).to_le_bytes());
+        file.extend_from_slice(&(n_properties as u32).to_le_bytes());
+
+        // The entities come first, then the properties, then the strings
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(&(offset_properties as u32).to_le_bytes());
+        file.extend_from_slice(&(offset_strings as u32).to_le_bytes());
+        file.extend(bin_entities);
+        file.extend(bin_properties);
+        file.extend(strings);
+
+        pad_to_4(&mut file);
+        Ok(file)
+    }
+}
+
+impl SkeletonPSX {
+    pub fn new() -> Self {
+        SkeletonPSX { bones: Vec::new() }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConvertError> {
+        let bytes = self.to_bytes().map_err(|err| err.with_path(path))?;
+        std::fs::write(path, bytes).map_err(|err| ConvertError::io(path, err))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ConvertError> {
+        // The names go right after the bones
+        let offset_names = self.bones.len() * BoneBinary::SIZE;
+        let mut names = Vec::<u8>::new();
+
+        let mut bin_bones = Vec::<u8>::new();
+        for bone in &self.bones {
+            bin_bones.extend_from_slice(&name_hash(&bone.name).to_le_bytes());
+            bin_bones.extend_from_slice(&((offset_names + names.len()) as u32).to_le_bytes());
+            bin_bones.extend_from_slice(&bone.parent.unwrap_or(NO_INDEX).to_le_bytes());
+            bin_bones.extend_from_slice(&bone.submesh_index.unwrap_or(NO_INDEX).to_le_bytes());
+            bin_bones.extend(bone.transform.to_bytes());
+            names.extend_from_slice(bone.name.as_bytes());
+            names.push(0);
+        }
+
+        // Create output buffer
+        let mut file = Vec::<u8>::new();
+        file.extend_from_slice("FSKL".as_bytes());
+        file.extend_from_slice(&SKL_VERSION.to_le_bytes());
+        file.extend_from_slice(&(self.bones.len() as u32).to_le_bytes());
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(&(offset_names as u32).to_le_bytes());
+        file.extend(bin_bones);
+        file.extend(names);
+
+        pad_to_4(&mut file);
+        Ok(file)
+    }
+}
+
+impl Default for AnimationCollectionPSX {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnimationCollectionPSX {
+    pub fn new() -> Self {
+        AnimationCollectionPSX {
+            clips: Vec::new(),
+            frame_rate: 30.0,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConvertError> {
+        let bytes = self.to_bytes().map_err(|err| err.with_path(path))?;
+        std::fs::write(path, bytes).map_err(|err| ConvertError::io(path, err))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ConvertError> {
+        // The clips come first, then the tracks, then the frames of every track, then the names
+        let n_tracks: usize = self.clips.iter().map(|clip| clip.tracks.len()).sum();
+        let n_frames: usize = self
+            .clips
+            .iter()
+            .flat_map(|clip| &clip.tracks)
+            .map(|track| track.frames.len())
+            .sum();
+        let offset_tracks = self.clips.len() * ClipBinary::SIZE;
+        let offset_frames = offset_tracks + n_tracks * TrackBinary::SIZE;
+        let offset_names = offset_frames + n_frames * TransformPSX::SIZE;
+
+        let mut bin_clips = Vec::<u8>::new();
+        let mut bin_tracks = Vec::<u8>::new();
+        let mut bin_frames = Vec::<u8>::new();
+        let mut names = Vec::<u8>::new();
+        let add_name = |name: &str, names: &mut Vec<u8>| {
+            let offset = (offset_names + names.len()) as u32;
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+            offset
+        };
+        let mut first_track = 0u32;
+        for clip in &self.clips {
+            bin_clips.extend_from_slice(&name_hash(&clip.name).to_le_bytes());
+            bin_clips.extend_from_slice(&add_name(&clip.name, &mut names).to_le_bytes());
+            bin_clips.extend_from_slice(&clip.n_frames.to_le_bytes());
+            bin_clips.extend_from_slice(&first_track.to_le_bytes());
+            bin_clips.extend_from_slice(&(clip.tracks.len() as u32).to_le_bytes());
+            first_track += clip.tracks.len() as u32;
+
+            for track in &clip.tracks {
+                // Every track needs a transform for every frame of its clip
+                if track.frames.len() != clip.n_frames as usize {
+                    return Err(ConvertError::malformed(
+                        format!("animation '{}'", clip.name),
+                        format!(
+                            "track '{}' has {} frames, but the clip has {}",
+                            track.name,
+                            track.frames.len(),
+                            clip.n_frames
+                        ),
+                    ));
+                }
+                let offset_track_frames = (offset_frames + bin_frames.len()) as u32;
+                bin_tracks.extend_from_slice(&name_hash(&track.name).to_le_bytes());
+                bin_tracks.extend_from_slice(&add_name(&track.name, &mut names).to_le_bytes());
+                bin_tracks.extend_from_slice(&track.bone_index.unwrap_or(NO_INDEX).to_le_bytes());
+                bin_tracks
+                    .extend_from_slice(&track.submesh_index.unwrap_or(NO_INDEX).to_le_bytes());
+                bin_tracks.extend_from_slice(&offset_track_frames.to_le_bytes());
+                for frame in &track.frames {
+                    bin_frames.extend(frame.to_bytes());
+                }
+            }
         }
 
-        Ok(0)
+        // Write the frame rate as 16.16 fixed point, like the scale in the .msh
+        let fixed_point_frame_rate =
+            to_fixed_point_16_16(self.frame_rate, "animations", "frame rate")?;
+
+        // Create output buffer
+        let mut file = Vec::<u8>::new();
+        file.extend_from_slice("FANM".as_bytes());
+        file.extend_from_slice(&ANM_VERSION.to_le_bytes());
+        file.extend_from_slice(&(self.clips.len() as u32).to_le_bytes());
+        file.extend_from_slice(&fixed_point_frame_rate.to_le_bytes());
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(&(offset_tracks as u32).to_le_bytes());
+        file.extend_from_slice(&(offset_frames as u32).to_le_bytes());
+        file.extend_from_slice(&(offset_names as u32).to_le_bytes());
+        file.extend(bin_clips);
+        file.extend(bin_tracks);
+        file.extend(bin_frames);
+        file.extend(names);
+
+        pad_to_4(&mut file);
+        Ok(file)
     }
 }
 
+impl Default for VertexAnimationPSX {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VertexAnimationPSX {
+    pub fn new() -> Self {
+        VertexAnimationPSX {
+            sequences: Vec::new(),
+            frame_rate: 30.0,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConvertError> {
+        let bytes = self.to_bytes().map_err(|err| err.with_path(path))?;
+        std::fs::write(path, bytes).map_err(|err| ConvertError::io(path, err))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ConvertError> {
+        // The sequences come first, then the offsets of every sequence, then the names.
+        // Each vertex offset is 6 bytes, so every sequence gets padded to keep the next one aligned
+        let deltas_size = |sequence: &MorphSequencePSX| {
+            let size: usize = sequence.frames.iter().map(|frame| frame.len() * 6).sum();
+            size.next_multiple_of(4)
+        };
+        let offset_deltas = self.sequences.len() * SequenceBinary::SIZE;
+        let offset_names = offset_deltas + self.sequences.iter().map(deltas_size).sum::<usize>();
+
+        let mut bin_sequences = Vec::<u8>::new();
+        let mut bin_deltas = Vec::<u8>::new();
+        let mut names = Vec::<u8>::new();
+        for sequence in &self.sequences {
+            // Every frame needs an offset for every vertex
+            let n_vertices = sequence.frames.first().map_or(0, |frame| frame.len());
+            if let Some(frame) = sequence
+                .frames
+                .iter()
+                .position(|frame| frame.len() != n_vertices)
+            {
+                return Err(ConvertError::malformed(
+                    format!("morph sequence '{}'", sequence.name),
+                    format!(
+                        "frame {} has {} vertices, but frame 0 has {}",
+                        frame,
+                        sequence.frames[frame].len(),
+                        n_vertices
+                    ),
+                ));
+            }
+            let flags = match sequence.animated {
+                true => SEQUENCE_ANIMATED,
+                false => 0,
+            };
+            bin_sequences.extend_from_slice(&name_hash(&sequence.name).to_le_bytes());
+            bin_sequences.extend_from_slice(&((offset_names + names.len()) as u32).to_le_bytes());
+            bin_sequences.extend_from_slice(&sequence.submesh_index.to_le_bytes());
+            bin_sequences.extend_from_slice(&(n_vertices as u32).to_le_bytes());
+            bin_sequences.extend_from_slice(&(sequence.frames.len() as u32).to_le_bytes());
+            bin_sequences.extend_from_slice(&flags.to_le_bytes());
+            bin_sequences
+                .extend_from_slice(&((offset_deltas + bin_deltas.len()) as u32).to_le_bytes());
+            for component in sequence.frames.iter().flatten().flatten() {
+                bin_deltas.extend_from_slice(&component.to_le_bytes());
+            }
+            pad_to_4(&mut bin_deltas);
+            names.extend_from_slice(sequence.name.as_bytes());
+            names.push(0);
+        }
+
+        // Write the frame rate as 16.16 fixed point, like in the .anm
+        let fixed_point_frame_rate =
+            to_fixed_point_16_16(self.frame_rate, "vertex animations", "frame rate")?;
+
+        // Create output buffer
+        let mut file = Vec::<u8>::new();
+        file.extend_from_slice("FVTA".as_bytes());
+        file.extend_from_slice(&VTA_VERSION.to_le_bytes());
+        file.extend_from_slice(&(self.sequences.len() as u32).to_le_bytes());
+        file.extend_from_slice(&fixed_point_frame_rate.to_le_bytes());
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(&(offset_deltas as u32).to_le_bytes());
+        file.extend_from_slice(&(offset_names as u32).to_le_bytes());
+        file.extend(bin_sequences);
+        file.extend(bin_deltas);
+        file.extend(names);
+
+        pad_to_4(&mut file);
+        Ok(file)
+    }
+}
+
+impl SequenceBinary {
+    /// Size of a SequenceBinary in the file, in bytes.
+    pub const SIZE: usize = 28;
+
+    pub fn from_bytes(buffer: &[u8]) -> Self {
+        SequenceBinary {
+            name_hash: u32_at(buffer, 0),
+            offset_name: u32_at(buffer, 4),
+            submesh_index: u32_at(buffer, 8),
+            n_vertices: u32_at(buffer, 12),
+            n_frames: u32_at(buffer, 16),
+            flags: u32_at(buffer, 20),
+            offset_deltas: u32_at(buffer, 24),
+        }
+    }
+}
+
+impl ClipBinary {
+    /// Size of a ClipBinary in the file, in bytes.
+    pub const SIZE: usize = 20;
+
+    pub fn from_bytes(buffer: &[u8]) -> Self {
+        ClipBinary {
+            name_hash: u32_at(buffer, 0),
+            offset_name: u32_at(buffer, 4),
+            n_frames: u32_at(buffer, 8),
+            first_track: u32_at(buffer, 12),
+            n_tracks: u32_at(buffer, 16),
+        }
+    }
+}
+
+impl TrackBinary {
+    /// Size of a TrackBinary in the file, in bytes.
+    pub const SIZE: usize = 20;
+
+    pub fn from_bytes(buffer: &[u8]) -> Self {
+        TrackBinary {
+            name_hash: u32_at(buffer, 0),
+            offset_name: u32_at(buffer, 4),
+            bone_index: u32_at(buffer, 8),
+            submesh_index: u32_at(buffer, 12),
+            offset_frames: u32_at(buffer, 16),
+        }
+    }
+}
+
+impl BoneBinary {
+    /// Size of a BoneBinary in the file, in bytes.
+    pub const SIZE: usize = 16 + TransformPSX::SIZE;
+
+    pub fn from_bytes(buffer: &[u8]) -> Self {
+        BoneBinary {
+            name_hash: u32_at(buffer, 0),
+            offset_name: u32_at(buffer, 4),
+            parent: u32_at(buffer, 8),
+            submesh_index: u32_at(buffer, 12),
+            transform: TransformPSX::from_bytes(&buffer[16..]),
+        }
+    }
+}
+
+impl EntityBinary {
+    /// Size of an EntityBinary in the file, in bytes.
+    pub const SIZE: usize = 16 + TransformPSX::SIZE;
+
+    pub fn from_bytes(buffer: &[u8]) -> Self {
+        EntityBinary {
+            name_hash: u32_at(buffer, 0),
+            offset_name: u32_at(buffer, 4),
+            transform: TransformPSX::from_bytes(&buffer[8..]),
+            first_property: u32_at(buffer, 8 + TransformPSX::SIZE),
+            n_properties: u32_at(buffer, 12 + TransformPSX::SIZE),
+        }
+    }
+}
+
+impl PropertyBinary {
+    /// Size of a PropertyBinary in the file, in bytes.
+    pub const SIZE: usize = 16;
+
+    pub fn from_bytes(buffer: &[u8]) -> Self {
+        PropertyBinary {
+            key_hash: u32_at(buffer, 0),
+            offset_key: u32_at(buffer, 4),
+            kind: u32_at(buffer, 8),
+            value: u32_at(buffer, 12),
+        }
+    }
+}
+
+/// Read a little-endian u32 at `offset` in `buffer`. Signed 32-bit fields are cast from this as well.
+pub fn u32_at(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buffer[offset],
+        buffer[offset + 1],
+        buffer[offset + 2],
+        buffer[offset + 3],
+    ])
+}
+
+/// Read a little-endian i16 at `offset` in `buffer`.
+pub fn i16_at(buffer: &[u8], offset: usize) -> i16 {
+    i16::from_le_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+/// Convert `value` to unsigned 16.16 fixed point. `context` and `name` say what didn't fit in the error.
+fn to_fixed_point_16_16(value: f32, context: &str, name: &str) -> Result<u32, ConvertError> {
+    let fixed_point = (value * 65536.0).round();
+    if !(0.0..=u32::MAX as f32).contains(&fixed_point) {
+        return Err(ConvertError::overflow(
+            context,
+            format!("the {name} {value} does not fit in 16.16 fixed point"),
+        ));
+    }
+    Ok(fixed_point as u32)
+}
+
+/// Pad `bytes` with zeroes to a multiple of 4. Every file ends like this, so it can be loaded straight into
+/// RAM after another file.
+fn pad_to_4(bytes: &mut Vec<u8>) {
+    while !bytes.len().is_multiple_of(4) {
+        bytes.push(0);
+    }
+}
+
+/// The hash used to look up names in the .msh name table: 32-bit FNV-1a over the UTF-8 bytes of the name.
+pub fn name_hash(name: &str) -> u32 {
+    let mut hash = 0x811C9DC5u32;
+    for byte in name.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
 impl MeshDesc {
+    /// Size of a MeshDesc in the file, in bytes.
+    pub const SIZE: usize = 32;
+
     pub fn from_bytes(buffer: &[u8]) -> Self {
-        let a = unsafe { &*(buffer.as_ptr() as *const MeshDesc) };
-        *a
+        MeshDesc {
+            vertex_start: u32_at(buffer, 0),
+            n_vertices: u32_at(buffer, 4),
+            x_min: i16_at(buffer, 8),
+            x_max: i16_at(buffer, 10),
+            y_min: i16_at(buffer, 12),
+            y_max: i16_at(buffer, 14),
+            z_min: i16_at(buffer, 16),
+            z_max: i16_at(buffer, 18),
+            origin_x: u32_at(buffer, 20) as i32,
+            origin_y: u32_at(buffer, 24) as i32,
+            origin_z: u32_at(buffer, 28) as i32,
+        }
     }
 }
 
@@ -195,15 +1035,20 @@ impl TextureCollectionPSX {
         }
     }
 
-    pub fn save(&self, path: &Path) -> std::io::Result<usize> {
-        // Open output file
-        let mut file = File::create(path)?;
+    pub fn save(&self, path: &Path) -> Result<(), ConvertError> {
+        let bytes = self.to_bytes().map_err(|err| err.with_path(path))?;
+        std::fs::write(path, bytes).map_err(|err| ConvertError::io(path, err))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ConvertError> {
+        // Create output buffer
+        let mut file = Vec::<u8>::new();
 
         // Write file magic
-        validate(file.write("FTXC".as_bytes()));
+        file.extend_from_slice("FTXC".as_bytes());
 
         // Write number of texture cells and palettes
-        validate(file.write(&(self.texture_cells.len() as u32).to_le_bytes()));
+        file.extend_from_slice(&(self.texture_cells.len() as u32).to_le_bytes());
 
         // Create binary data buffers for each part
         let mut bin_texture_cell_descs: Vec<u8> = Vec::new();
@@ -234,9 +1079,6 @@ impl TextureCollectionPSX {
                 let n_bytes_to_add =
                     ((curr_position + (grid_align - 1)) & !(grid_align - 1)) - curr_position;
 
-                println!("curr_position: {curr_position}");
-                println!("n_bytes_to_add: {n_bytes_to_add}");
-
                 // Pad to this align
                 bin_texture_data.resize(bin_texture_data.len() + n_bytes_to_add as usize, 0);
 
@@ -244,7 +1086,17 @@ impl TextureCollectionPSX {
                 bin_texture_data.extend(&cell.texture_data);
 
                 // Write texture offset
-                bin_texture_cell_descs.push(((curr_position + n_bytes_to_add) / 2048) as u8);
+                let sector_offset = (curr_position + n_bytes_to_add) / 2048;
+                if sector_offset > u8::MAX as u32 || i > u8::MAX as usize {
+                    return Err(ConvertError::overflow(
+                        format!(
+                            "texture '{}'",
+                            self.texture_names.get(i).map_or("", |name| name.as_str())
+                        ),
+                        "the texture data does not fit in 256 sectors",
+                    ));
+                }
+                bin_texture_cell_descs.push(sector_offset as u8);
 
                 // Write palette index
                 bin_texture_cell_descs.extend_from_slice(&(i as u8).to_le_bytes());
@@ -262,11 +1114,11 @@ impl TextureCollectionPSX {
         let mut cursor: u32 = 0;
 
         // Write offset to texture cell descs
-        validate(file.write(&(cursor).to_le_bytes()));
+        file.extend_from_slice(&(cursor).to_le_bytes());
         cursor += bin_texture_cell_descs.len() as u32;
 
         // Write offset to palettes
-        validate(file.write(&(cursor).to_le_bytes()));
+        file.extend_from_slice(&(cursor).to_le_bytes());
         cursor += bin_palettes.len() as u32;
 
         // Align the texture data to a CD sector. This allows for some neat optimizations
@@ -275,30 +1127,35 @@ impl TextureCollectionPSX {
         cursor += bytes_to_pad;
 
         // Write offset to textures
-        validate(file.write(&(cursor).to_le_bytes()));
+        file.extend_from_slice(&(cursor).to_le_bytes());
         //cursor += bin_texture_data.len() as u32;
 
-        // todo: name table
-        validate(file.write(&(0u32).to_le_bytes()));
+        // The texture names aren't written yet, so there's no name table
+        file.extend_from_slice(&NO_TABLE.to_le_bytes());
 
         // Write the raw buffers now, in the right order
-        validate(file.write(bin_texture_cell_descs.as_slice()));
-        validate(file.write(bin_palettes.as_slice()));
+        file.extend_from_slice(bin_texture_cell_descs.as_slice());
+        file.extend_from_slice(bin_palettes.as_slice());
 
         // Pad with zeroes
         for _ in 0..bytes_to_pad {
-            validate(file.write(&[0u8]));
+            file.extend_from_slice(&[0u8]);
         }
 
-        validate(file.write(bin_texture_data.as_slice()));
+        file.extend_from_slice(bin_texture_data.as_slice());
 
-        Ok(0)
+        Ok(file)
     }
 }
 
 impl TextureCellBinary {
     pub fn from_bytes(buffer: &[u8]) -> Self {
-        let a = unsafe { &*(buffer.as_ptr() as *const Self) };
-        *a
+        TextureCellBinary {
+            sector_offset_texture: buffer[0],
+            palette_index: buffer[1],
+            texture_width: buffer[2],
+            texture_height: buffer[3],
+            avg_color: u32_at(buffer, 4),
+        }
     }
 }
@@ -0,0 +1,114 @@
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+
+use crate::{error::ConvertError, helpers::matches_pattern};
+
+/// Refers to a scene in a glTF file, either by its index or by its name.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SceneRef {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for SceneRef {
+    type Err = String;
+
+    /// Anything that's a number is an index, everything else is a name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(String::from("the scene name can't be empty"));
+        }
+        match s.parse::<usize>() {
+            Ok(index) => Ok(SceneRef::Index(index)),
+            Err(_) => Ok(SceneRef::Name(String::from(s))),
+        }
+    }
+}
+
+/// Find the scene to convert. Without a `scene_ref` this is the default scene of the file,
+/// or the first scene if the file doesn't have a default. Fails if the file has no scenes at all.
+pub fn find_scene<'a>(
+    document: &'a gltf::Document,
+    scene_ref: Option<&SceneRef>,
+) -> Result<gltf::Scene<'a>, ConvertError> {
+    let Some(scene_ref) = scene_ref else {
+        return document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .ok_or_else(|| ConvertError::unsupported("file", "the file has no scenes"));
+    };
+
+    let scene = match scene_ref {
+        SceneRef::Index(index) => document.scenes().nth(*index),
+        SceneRef::Name(name) => document
+            .scenes()
+            .find(|scene| scene.name() == Some(name.as_str())),
+    };
+    match scene {
+        Some(scene) => Ok(scene),
+        None => Err(ConvertError::unsupported(
+            "options",
+            match scene_ref {
+                SceneRef::Index(index) => format!("scene {index}, the file doesn't have it"),
+                SceneRef::Name(name) => format!("scene '{name}', the file doesn't have it"),
+            },
+        )),
+    }
+}
+
+/// A name for a scene that can safely be used in a file name. Unnamed scenes are called `scene<index>`.
+pub fn scene_file_name(scene: &gltf::Scene) -> String {
+    match scene.name() {
+        Some(name) if !name.is_empty() => name
+            .chars()
+            .map(
+                |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    true => c,
+                    false => '_',
+                },
+            )
+            .collect(),
+        _ => format!("scene{}", scene.index()),
+    }
+}
+
+/// Decides which nodes of a scene get converted. Excluding a node also excludes its children,
+/// and including a node also includes its children.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeFilter {
+    /// Only convert nodes with a name that matches one of these patterns. Everything is converted if this is empty.
+    pub include: Vec<String>,
+    /// Skip nodes with a name that matches one of these patterns.
+    pub exclude: Vec<String>,
+}
+
+impl NodeFilter {
+    /// Whether the node and its children should be skipped. Nodes with `"psx_export": false` in
+    /// their extras are always skipped.
+    pub fn excludes(&self, node: &gltf::Node) -> bool {
+        let name = node.name().unwrap_or("");
+        self.exclude
+            .iter()
+            .any(|pattern| matches_pattern(pattern, name))
+            || node_extras(node).get("psx_export") == Some(&Value::Bool(false))
+    }
+
+    /// Whether the node and its children pass the include patterns.
+    pub fn includes(&self, node: &gltf::Node) -> bool {
+        let name = node.name().unwrap_or("");
+        self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| matches_pattern(pattern, name))
+    }
+}
+
+/// The extras of a node as a JSON object. Returns an empty object if there are no extras, or if they're not an object.
+pub fn node_extras(node: &gltf::Node) -> Map<String, Value> {
+    node.extras()
+        .as_ref()
+        .and_then(|extras| serde_json::from_str(extras.get()).ok())
+        .unwrap_or_default()
+}
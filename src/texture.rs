@@ -1,5 +1,5 @@
+use crate::error::ConvertError;
 use crate::helpers::*;
-use std::path::Path;
 
 pub struct Texture {
     pub width: usize,
@@ -32,10 +32,36 @@ pub struct Sampler {
 }
 
 pub struct Material {
+    pub name: String,
     pub texture: Texture,
     pub sampler: Sampler,
 }
 
+impl Material {
+    /// A material with a plain white texture, for when there's no base texture to use.
+    pub fn white(name: String) -> Self {
+        Material {
+            name,
+            texture: Texture {
+                width: 64,
+                height: 64,
+                depth: 1,
+                data: vec![0xFFFFFFFFu32; 64 * 64],
+                mipmap_offsets: vec![0usize; 1],
+                avg_color: 0xFFFFFFFFu32,
+            },
+            sampler: Sampler {
+                filter_mode_mag: FilterMode::Point,
+                filter_mode_min: FilterMode::Point,
+                filter_mode_mipmap: FilterMode::Point,
+                wrap_mode_s: WrapMode::Clamp,
+                wrap_mode_t: WrapMode::Clamp,
+                mipmap_enabled: false,
+            },
+        }
+    }
+}
+
 #[derive(Clone)]
 enum PixelComp {
     Skip,
@@ -46,62 +72,6 @@ enum PixelComp {
 }
 
 impl Texture {
-    pub fn load(path: &Path) -> Self {
-        //Load image
-        let loaded_image = stb_image::image::load(path);
-
-        //Map the image data to argb8 format
-        if let stb_image::image::LoadResult::ImageU8(image) = loaded_image {
-            if image.depth == 4 {
-                let data = (0..image.data.len() / 4)
-                    .map(|id| {
-                        colour_rgba(
-                            image.data[id * 4 + 3],
-                            image.data[id * 4],
-                            image.data[id * 4 + 1],
-                            image.data[id * 4 + 2],
-                        )
-                    })
-                    .collect();
-                let mut result = Self {
-                    width: image.width,
-                    height: image.height,
-                    depth: image.depth,
-                    data,
-                    mipmap_offsets: vec![0; 1],
-                    avg_color: 0xFFFFFFFFu32,
-                };
-                result.calculate_avg_color();
-                result
-            } else if image.depth == 3 {
-                let data = (0..image.data.len() / 3)
-                    .map(|id| {
-                        colour_rgba(
-                            255,
-                            image.data[id * 3],
-                            image.data[id * 3 + 1],
-                            image.data[id * 3 + 2],
-                        )
-                    })
-                    .collect();
-                let mut result = Self {
-                    width: image.width,
-                    height: image.height,
-                    depth: image.depth,
-                    data,
-                    mipmap_offsets: vec![0; 1],
-                    avg_color: 0xFFFFFFFFu32,
-                };
-                result.calculate_avg_color();
-                result
-            } else {
-                panic!("Unsupported texture type");
-            }
-        } else {
-            panic!("Unsupported texture type");
-        }
-    }
-
     //Get ARGB value from a UV coordinate
     pub fn argb_at_uv(
         &self,
@@ -174,7 +144,11 @@ impl Texture {
         }
     }
 
-    pub fn load_texture_from_gltf_image(image: &gltf::image::Data) -> Texture {
+    /// Convert a decoded glTF image to a texture. `context` names the material in errors.
+    pub fn load_texture_from_gltf_image(
+        image: &gltf::image::Data,
+        context: &str,
+    ) -> Result<Texture, ConvertError> {
         // Get pixel swizzle pattern
         let swizzle_pattern = match image.format {
             gltf::image::Format::R8 => vec![PixelComp::Red],
@@ -211,8 +185,12 @@ impl Texture {
                 PixelComp::Skip,
                 PixelComp::Alpha,
             ],
-            gltf::image::Format::R32G32B32FLOAT => unimplemented!(),
-            gltf::image::Format::R32G32B32A32FLOAT => unimplemented!(),
+            gltf::image::Format::R32G32B32FLOAT | gltf::image::Format::R32G32B32A32FLOAT => {
+                return Err(ConvertError::unsupported(
+                    context,
+                    "floating point textures",
+                ))
+            }
         };
         let mut result = Texture {
             width: image.width as usize,
@@ -250,7 +228,7 @@ impl Texture {
             avg_color: 0,
         };
         result.calculate_avg_color();
-        result
+        Ok(result)
     }
 
     pub fn generate_mipmaps(&mut self) {
@@ -325,13 +303,14 @@ impl Texture {
                 avg_b += bytes[2] as u32;
                 div += 1;
             }
+            // An empty image averages to black
+            let div = div.max(1);
             avg_r = (avg_r / div).clamp(0, 255);
             avg_g = (avg_g / div).clamp(0, 255);
             avg_b = (avg_b / div).clamp(0, 255);
 
             (avg_r << 0) | (avg_g << 8) | (avg_b << 16) | (0xFF << 24)
         };
-        println!("calculate avg_color: {:x}", self.avg_color);
     }
 }
 
@@ -0,0 +1,1047 @@
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use glam::Vec3;
+use gltf2psx::{
+    convert_gltf, convert_gltf_scenes, convert_gltf_slice, export_msh,
+    inspect::{debug_anm, debug_ent, debug_msh, debug_skl, debug_vta},
+    is_gltf_path, output_base_path,
+    psx_structs::{PropertyValue, VertexPSX},
+    scene::NodeFilter,
+    AxisMapping, ConvertError, ConvertOptions, ConvertOutput, Partition, Scale, SceneRef,
+};
+
+fn asset(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/assets")
+        .join(name)
+}
+
+#[test]
+fn convert_gltf_with_embedded_buffer() {
+    let output = convert_gltf(&asset("quad.gltf"), &ConvertOptions::default()).unwrap();
+    assert_eq!(output.model.meshes.len(), 1);
+    assert_eq!(output.model.meshes[0].verts.len(), 6);
+    assert_eq!(output.textures.texture_cells.len(), 1);
+
+    // The node is moved one unit along Z, which is 1024 in fixed point
+    assert!(output.model.meshes[0].verts.iter().all(|v| v.pos_z == 1024));
+}
+
+#[test]
+fn convert_glb() {
+    let gltf = convert_gltf(&asset("quad.gltf"), &ConvertOptions::default()).unwrap();
+    let glb = convert_gltf(&asset("quad.glb"), &ConvertOptions::default()).unwrap();
+    assert_eq!(
+        gltf.model.to_bytes().unwrap(),
+        glb.model.to_bytes().unwrap()
+    );
+    assert_eq!(
+        gltf.textures.to_bytes().unwrap(),
+        glb.textures.to_bytes().unwrap()
+    );
+}
+
+#[test]
+fn convert_from_memory() {
+    for name in ["quad.gltf", "quad.glb"] {
+        let bytes = std::fs::read(asset(name)).unwrap();
+        let output = convert_gltf_slice(&bytes, &ConvertOptions::default()).unwrap();
+        assert_eq!(output.model.meshes[0].verts.len(), 6);
+    }
+}
+
+#[test]
+fn export_glb_output_naming() {
+    let out_dir = std::env::temp_dir().join(format!("gltf2psx_{}_export", std::process::id()));
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let path_out = output_base_path(&asset("quad.glb"), Some(&out_dir), None);
+    let result = export_msh(&asset("quad.glb"), &path_out, &ConvertOptions::default());
+    let written = ["quad.msh", "quad.txc", "quad.ent"].map(|name| out_dir.join(name).exists());
+    std::fs::remove_dir_all(&out_dir).unwrap();
+    result.unwrap();
+    assert_eq!(written, [true, true, true]);
+}
+
+#[test]
+fn output_paths() {
+    assert!(is_gltf_path(Path::new("level.gltf")));
+    assert!(is_gltf_path(Path::new("level.GLB")));
+    assert!(!is_gltf_path(Path::new("level.msh")));
+    assert_eq!(
+        output_base_path(Path::new("assets/level.v2.glb"), None, None),
+        Path::new("assets/level.v2")
+    );
+    assert_eq!(
+        output_base_path(Path::new("level.glb"), Some(Path::new("out")), Some("lvl")),
+        Path::new("out/lvl")
+    );
+}
+
+#[test]
+fn vertex_overflow() {
+    // At this scale the quad ends up at Z = 40000, which doesn't fit in 16 bits
+    let mut options = ConvertOptions {
+        scale: Scale::Fixed(40000.0),
+        ..Default::default()
+    };
+    let output = convert_gltf(&asset("quad.gltf"), &options).unwrap();
+    assert_eq!(output.warnings.len(), 1);
+    assert!(output.warnings[0].contains("'quad'"));
+
+    // Moving the origin of the submesh to its center makes it fit again
+    options.relative_origins = true;
+    let output = convert_gltf(&asset("quad.gltf"), &options).unwrap();
+    assert!(output.warnings.is_empty());
+    assert_eq!(output.model.meshes[0].origin[2], 40000);
+    assert!(output.model.meshes[0].verts.iter().all(|v| v.pos_z == 0));
+}
+
+#[test]
+fn convert_non_indexed() {
+    let output = convert_gltf(&asset("non_indexed.gltf"), &ConvertOptions::default()).unwrap();
+    assert_eq!(output.model.meshes.len(), 1);
+    assert_eq!(output.model.meshes[0].verts.len(), 6);
+
+    // Should end up with the same triangles as the indexed quad
+    let indexed = convert_gltf(&asset("quad.gltf"), &ConvertOptions::default()).unwrap();
+    assert_eq!(
+        output.model.to_bytes().unwrap(),
+        indexed.model.to_bytes().unwrap()
+    );
+}
+
+#[test]
+fn convert_u32_indices() {
+    let output = convert_gltf(&asset("u32_indices.gltf"), &ConvertOptions::default()).unwrap();
+    let indexed = convert_gltf(&asset("quad.gltf"), &ConvertOptions::default()).unwrap();
+    assert_eq!(
+        output.model.to_bytes().unwrap(),
+        indexed.model.to_bytes().unwrap()
+    );
+}
+
+/// Wrap a glTF JSON document and its binary buffer in a .glb container.
+fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+    let mut json = json.as_bytes().to_vec();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let mut bin = bin.to_vec();
+    bin.resize(bin.len().next_multiple_of(4), 0);
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"glTF");
+    bytes.extend_from_slice(&2u32.to_le_bytes());
+    bytes.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+    bytes.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(b"JSON");
+    bytes.extend(json);
+    bytes.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(b"BIN\0");
+    bytes.extend(bin);
+    bytes
+}
+
+#[test]
+fn convert_more_than_65535_vertices() {
+    // Vertex i sits at (i % 1000, i / 1000, 0), and the U32 indices use the vertices back to front
+    let n_vertices = 3 * 23334;
+    let mut bin = Vec::new();
+    for i in 0..n_vertices {
+        for value in [(i % 1000) as f32, (i / 1000) as f32, 0.0] {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    for i in (0..n_vertices as u32).rev() {
+        bin.extend_from_slice(&i.to_le_bytes());
+    }
+    let json = format!(
+        r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scene": 0,
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [{{ "mesh": 0, "name": "big" }}],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1 }}] }}],
+            "buffers": [{{ "byteLength": {buffer_length} }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": {positions_length} }},
+                {{ "buffer": 0, "byteOffset": {positions_length}, "byteLength": {indices_length} }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": {n_vertices}, "type": "VEC3",
+                   "min": [0, 0, 0], "max": [999, {max_y}, 0] }},
+                {{ "bufferView": 1, "componentType": 5125, "count": {n_vertices}, "type": "SCALAR" }}
+            ]
+        }}"#,
+        buffer_length = bin.len(),
+        positions_length = n_vertices * 12,
+        indices_length = n_vertices * 4,
+        max_y = (n_vertices - 1) / 1000,
+    );
+    let options = ConvertOptions {
+        scale: Scale::Fixed(1.0),
+        axes: AxisMapping::Y_UP,
+        partition: Partition::None,
+        ..Default::default()
+    };
+    let output = convert_gltf_slice(&glb(&json, &bin), &options).unwrap();
+
+    // Every vertex made it through, and every index above 65535 was read exactly
+    let verts = &output.model.meshes[0].verts;
+    assert_eq!(verts.len(), n_vertices);
+    for (k, vertex) in verts.iter().enumerate() {
+        let index = n_vertices - 1 - k;
+        assert_eq!(
+            (vertex.pos_x, vertex.pos_y),
+            ((index % 1000) as i16, (index / 1000) as i16)
+        );
+    }
+    let msh = output.model.to_bytes().unwrap();
+    assert!(debug_msh(&mut Cursor::new(msh)).unwrap());
+}
+
+#[test]
+fn reject_accessor_outside_of_its_buffer_view() {
+    // The accessor claims four billion positions in a buffer view of a single one, which has to be
+    // caught before anything gets allocated for it
+    let bin = [0u8; 12];
+    let json = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0 }],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+        "buffers": [{ "byteLength": 12 }],
+        "bufferViews": [{ "buffer": 0, "byteOffset": 0, "byteLength": 12 }],
+        "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 4000000000, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [0, 0, 0] }]
+    }"#;
+    let result = convert_gltf_slice(&glb(json, &bin), &ConvertOptions::default());
+    assert!(matches!(result, Err(ConvertError::MalformedGltf { .. })));
+}
+
+#[test]
+fn convert_interleaved() {
+    // Positions and UVs share a buffer view with a stride, and the indices start at an offset
+    let output = convert_gltf(&asset("interleaved.gltf"), &ConvertOptions::default()).unwrap();
+    let indexed = convert_gltf(&asset("quad.gltf"), &ConvertOptions::default()).unwrap();
+    assert_eq!(
+        output.model.to_bytes().unwrap(),
+        indexed.model.to_bytes().unwrap()
+    );
+}
+
+#[test]
+fn convert_normalized_attributes() {
+    // UVs are stored as normalized u16 going from 0 to 0.5, and colors as normalized u8 at 50% grey
+    let output = convert_gltf(&asset("quantized.gltf"), &ConvertOptions::default()).unwrap();
+    let verts = &output.model.meshes[0].verts;
+    assert!(verts.iter().all(|v| v.tex_u <= 128 && v.tex_v <= 128));
+    assert!(verts.iter().any(|v| v.tex_u == 127));
+
+    // 0.5 in linear space is about 186 after gamma correction
+    assert!(verts.iter().all(|v| v.color_r == 186));
+}
+
+#[test]
+fn convert_sparse() {
+    // The positions only exist as sparse values on top of zeroes, and one UV is replaced
+    let output = convert_gltf(&asset("sparse.gltf"), &ConvertOptions::default()).unwrap();
+    let indexed = convert_gltf(&asset("quad.gltf"), &ConvertOptions::default()).unwrap();
+    assert_eq!(
+        output.model.to_bytes().unwrap(),
+        indexed.model.to_bytes().unwrap()
+    );
+}
+
+/// The triangles of a submesh, each rotated to start at its smallest vertex so the winding can be compared
+fn triangles(verts: &[VertexPSX]) -> Vec<Vec<Vec<u8>>> {
+    verts
+        .chunks(3)
+        .map(|triangle| {
+            let mut bytes: Vec<Vec<u8>> = triangle.iter().map(|v| v.get_bytes()).collect();
+            let first = (0..3).min_by_key(|i| bytes[*i].clone()).unwrap();
+            bytes.rotate_left(first);
+            bytes
+        })
+        .collect()
+}
+
+#[test]
+fn convert_strips_and_fans() {
+    // A strip and a fan that both make up the quad, and a line that gets skipped
+    let output = convert_gltf(&asset("modes.gltf"), &ConvertOptions::default()).unwrap();
+    assert_eq!(output.warnings.len(), 1);
+    assert!(output.warnings[0].contains("Lines"));
+
+    let quad = convert_gltf(&asset("quad.gltf"), &ConvertOptions::default()).unwrap();
+    let verts = &output.model.meshes[0].verts;
+    assert_eq!(verts.len(), 12);
+    for half in verts.chunks(6) {
+        assert_eq!(triangles(half), triangles(&quad.model.meshes[0].verts));
+    }
+}
+
+#[test]
+fn convert_rgb_colors_with_base_color_factor() {
+    // RGB vertex colors at 0.5, with a material that halves green and blue
+    let output = convert_gltf(&asset("tinted.gltf"), &ConvertOptions::default()).unwrap();
+    for vertex in &output.model.meshes[0].verts {
+        // 0.5 and 0.25 in linear space, after gamma correction
+        assert_eq!(
+            (vertex.color_r, vertex.color_g, vertex.color_b),
+            (186, 135, 135)
+        );
+    }
+}
+
+#[test]
+fn convert_materials_by_index() {
+    // Two different materials with the same name, and a primitive without a material
+    let output = convert_gltf(&asset("materials.gltf"), &ConvertOptions::default()).unwrap();
+    let mut names = output.textures.texture_names.clone();
+    names.sort();
+    assert_eq!(names, ["default", "same", "same"]);
+
+    // Every material gets its own texture id
+    let mut texture_ids: Vec<u8> = output.model.meshes[0]
+        .verts
+        .iter()
+        .map(|v| v.texture_id)
+        .collect();
+    texture_ids.dedup();
+    assert_eq!(texture_ids.len(), 3);
+}
+
+#[test]
+fn reproducible_output() {
+    // Several materials, and small grid cells so the quads get split into several submeshes
+    let options = ConvertOptions {
+        partition: Partition::Grid(Vec3::splat(0.25)),
+        ..Default::default()
+    };
+    let first = convert_gltf(&asset("materials.gltf"), &options).unwrap();
+    assert!(first.model.meshes.len() > 1);
+    for _ in 0..4 {
+        let output = convert_gltf(&asset("materials.gltf"), &options).unwrap();
+        assert_eq!(
+            output.model.to_bytes().unwrap(),
+            first.model.to_bytes().unwrap()
+        );
+        assert_eq!(
+            output.textures.to_bytes().unwrap(),
+            first.textures.to_bytes().unwrap()
+        );
+    }
+
+    // The default material comes first, then the materials in glTF order
+    assert_eq!(first.textures.texture_names, ["default", "same", "same"]);
+}
+
+#[test]
+fn parse_axis_mappings() {
+    assert_eq!("y-down".parse(), Ok(AxisMapping::Y_DOWN));
+    assert_eq!("y-up".parse(), Ok(AxisMapping::Y_UP));
+    assert_eq!("z-up".parse(), Ok(AxisMapping::Z_UP));
+    assert_eq!("-x,-y,+z".parse(), Ok(AxisMapping::Y_DOWN));
+    assert_eq!("X, -Z, Y".parse(), Ok(AxisMapping::Z_UP));
+    for invalid in ["", "up", "x,y", "x,y,z,x", "x,x,z", "w,y,z", "--x,y,z"] {
+        assert!(
+            invalid.parse::<AxisMapping>().is_err(),
+            "'{invalid}' was accepted"
+        );
+    }
+
+    // Only mappings with an odd number of negated or swapped axes mirror the model
+    assert!(!AxisMapping::Y_DOWN.flips_handedness());
+    assert!(!AxisMapping::Z_UP.flips_handedness());
+    assert!("-x,y,z".parse::<AxisMapping>().unwrap().flips_handedness());
+    assert!("y,x,z".parse::<AxisMapping>().unwrap().flips_handedness());
+}
+
+#[test]
+fn convert_axes_and_winding() {
+    // The first triangle of the quad goes from (0, 0, 1) to (1, 0, 1) to (0, 1, 1), and faces +Z
+    let first_triangle = |axes: &str| {
+        let options = ConvertOptions {
+            axes: axes.parse().unwrap(),
+            partition: Partition::None,
+            ..Default::default()
+        };
+        let output = convert_gltf(&asset("quad.gltf"), &options).unwrap();
+        let verts = &output.model.meshes[0].verts;
+        [0, 1, 2].map(|i| {
+            Vec3::new(
+                verts[i].pos_x as f32,
+                verts[i].pos_y as f32,
+                verts[i].pos_z as f32,
+            )
+        })
+    };
+    let normal = |[a, b, c]: [Vec3; 3]| (b - a).cross(c - a).normalize();
+
+    let y_up = first_triangle("y-up");
+    assert_eq!(
+        y_up,
+        [
+            Vec3::new(0.0, 0.0, 1024.0),
+            Vec3::new(1024.0, 0.0, 1024.0),
+            Vec3::new(0.0, 1024.0, 1024.0),
+        ]
+    );
+    assert_eq!(normal(y_up), Vec3::Z);
+
+    // glTF +Z points towards -Y with Z up
+    let z_up = first_triangle("z-up");
+    assert_eq!(
+        z_up,
+        [
+            Vec3::new(0.0, -1024.0, 0.0),
+            Vec3::new(1024.0, -1024.0, 0.0),
+            Vec3::new(0.0, -1024.0, 1024.0),
+        ]
+    );
+    assert_eq!(normal(z_up), Vec3::NEG_Y);
+
+    // Mirroring X would turn the triangle around, so the last two vertices get swapped to keep it facing +Z
+    let mirrored = first_triangle("-x,y,z");
+    assert_eq!(
+        mirrored,
+        [
+            Vec3::new(0.0, 0.0, 1024.0),
+            Vec3::new(0.0, 1024.0, 1024.0),
+            Vec3::new(-1024.0, 0.0, 1024.0),
+        ]
+    );
+    assert_eq!(normal(mirrored), Vec3::Z);
+}
+
+#[test]
+fn auto_fit_scale() {
+    // The floor goes up to 4 units, so that has to become the largest coordinate that fits in 16 bits
+    let options = ConvertOptions {
+        scale: Scale::AutoFit,
+        ..Default::default()
+    };
+    let output = convert_gltf(&asset("grid.gltf"), &options).unwrap();
+    assert!(output.warnings.is_empty());
+    assert!((output.model.scale - 32767.0 / 4.0).abs() < 1.0 / 65536.0);
+    let largest = output
+        .model
+        .meshes
+        .iter()
+        .flat_map(|mesh| &mesh.verts)
+        .flat_map(|v| [v.pos_x, v.pos_y, v.pos_z])
+        .map(|coordinate| coordinate.unsigned_abs())
+        .max()
+        .unwrap();
+    assert!((32766..=32767).contains(&largest));
+}
+
+/// The number of triangles in each submesh, and every triangle of the model in a fixed order.
+fn partitioned(output: &ConvertOutput) -> (Vec<usize>, Vec<Vec<Vec<u8>>>) {
+    let counts = output
+        .model
+        .meshes
+        .iter()
+        .map(|mesh| mesh.verts.len() / 3)
+        .collect();
+    let mut all: Vec<Vec<Vec<u8>>> = output
+        .model
+        .meshes
+        .iter()
+        .flat_map(|mesh| triangles(&mesh.verts))
+        .collect();
+    all.sort();
+    (counts, all)
+}
+
+#[test]
+fn partition_strategies() {
+    // A 4x4 floor of quads, 32 triangles in total
+    let convert = |partition: Partition| {
+        let options = ConvertOptions {
+            partition,
+            ..Default::default()
+        };
+        partitioned(&convert_gltf(&asset("grid.gltf"), &options).unwrap())
+    };
+    let (counts, everything) = convert(Partition::None);
+    assert_eq!(counts, [32]);
+
+    // The octree splits the floor into its four quadrants, and stops there
+    let (counts, all) = convert(Partition::Octree {
+        max_triangles: 8,
+        max_depth: 8,
+    });
+    assert_eq!(counts, [8, 8, 8, 8]);
+    assert_eq!(all, everything);
+
+    // Without any depth left, nothing gets split
+    let (counts, all) = convert(Partition::Octree {
+        max_triangles: 8,
+        max_depth: 0,
+    });
+    assert_eq!(counts, [32]);
+    assert_eq!(all, everything);
+
+    // The kd-tree halves the triangles until they fit
+    let (counts, all) = convert(Partition::KdTree { max_triangles: 5 });
+    assert_eq!(counts, [4, 4, 4, 4, 4, 4, 4, 4]);
+    assert_eq!(all, everything);
+
+    // Small grid cells split it up too
+    let (counts, all) = convert(Partition::Grid(Vec3::ONE));
+    assert!(counts.len() > 1);
+    assert_eq!(counts.iter().sum::<usize>(), 32);
+    assert_eq!(all, everything);
+}
+
+#[test]
+fn reject_bad_partitions() {
+    for partition in [
+        Partition::Grid(Vec3::ZERO),
+        Partition::Grid(Vec3::new(1.0, -1.0, 1.0)),
+        Partition::Grid(Vec3::new(1.0, f32::NAN, 1.0)),
+        Partition::Octree {
+            max_triangles: 1,
+            max_depth: 100000,
+        },
+    ] {
+        let options = ConvertOptions {
+            partition: partition.clone(),
+            ..Default::default()
+        };
+        let result = convert_gltf(&asset("grid.gltf"), &options);
+        assert!(
+            matches!(result, Err(ConvertError::Unsupported { .. })),
+            "{partition:?} was accepted"
+        );
+    }
+}
+
+#[test]
+fn convert_scenes() {
+    // The file has no default scene, so the first scene gets used
+    let output = convert_gltf(&asset("scenes.gltf"), &ConvertOptions::default()).unwrap();
+    assert!(output.model.meshes[0].verts.iter().all(|v| v.pos_z == 1024));
+
+    for scene in ["cutscene", "1"] {
+        let options = ConvertOptions {
+            scene: Some(scene.parse().unwrap()),
+            ..Default::default()
+        };
+        let output = convert_gltf(&asset("scenes.gltf"), &options).unwrap();
+        assert!(output.model.meshes[0].verts.iter().all(|v| v.pos_z == 2048));
+    }
+
+    let options = ConvertOptions {
+        scene: Some(SceneRef::Name(String::from("credits"))),
+        ..Default::default()
+    };
+    assert!(convert_gltf(&asset("scenes.gltf"), &options).is_err());
+
+    let outputs = convert_gltf_scenes(&asset("scenes.gltf"), &ConvertOptions::default()).unwrap();
+    let names: Vec<&str> = outputs.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["gameplay", "cutscene"]);
+}
+
+#[test]
+fn reject_file_without_scenes() {
+    // The quad is only in the meshes, without a scene that places it
+    let result = convert_gltf(&asset("no_scenes.gltf"), &ConvertOptions::default());
+    assert!(matches!(result, Err(ConvertError::Unsupported { .. })));
+    let result = convert_gltf_scenes(&asset("no_scenes.gltf"), &ConvertOptions::default());
+    assert!(matches!(result, Err(ConvertError::Unsupported { .. })));
+}
+
+#[test]
+fn keep_named_nodes() {
+    // "lever_A" has no mesh itself, but its child does
+    let options = ConvertOptions {
+        keep_nodes: vec![String::from("door_*"), String::from("lever_?")],
+        ..Default::default()
+    };
+    let output = convert_gltf(&asset("objects.gltf"), &options).unwrap();
+    let names: Vec<Option<&str>> = output
+        .model
+        .meshes
+        .iter()
+        .map(|mesh| mesh.name.as_deref())
+        .collect();
+    assert_eq!(names, [None, Some("door_01"), Some("lever_A")]);
+    assert!(output.model.meshes.iter().all(|mesh| mesh.verts.len() == 6));
+
+    // The name table has to pass the inspector
+    let msh = output.model.to_bytes().unwrap();
+    assert!(debug_msh(&mut Cursor::new(msh)).unwrap());
+}
+
+#[test]
+fn warn_about_duplicate_submesh_names() {
+    // Two kept nodes share the name "door", so the name table can't tell them apart
+    let bin: Vec<u8> = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    let json = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0, 1] }],
+        "nodes": [{ "mesh": 0, "name": "door" }, { "mesh": 0, "name": "door", "translation": [2, 0, 0] }],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+        "buffers": [{ "byteLength": 36 }],
+        "bufferViews": [{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }],
+        "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [1, 1, 0] }]
+    }"#;
+    let options = ConvertOptions {
+        keep_nodes: vec![String::from("door")],
+        ..Default::default()
+    };
+    let output = convert_gltf_slice(&glb(json, &bin), &options).unwrap();
+    assert_eq!(output.model.meshes.len(), 2);
+    assert_eq!(
+        output.warnings,
+        ["submesh 1: the hash of its name 'door' is the same as the one of submesh 0, so it can't be looked up by name"]
+    );
+}
+
+#[test]
+fn filter_nodes() {
+    let convert = |include: &[&str], exclude: &[&str]| {
+        let options = ConvertOptions {
+            partition: Partition::None,
+            node_filter: NodeFilter {
+                include: include.iter().map(|pattern| pattern.to_string()).collect(),
+                exclude: exclude.iter().map(|pattern| pattern.to_string()).collect(),
+            },
+            ..Default::default()
+        };
+        let output = convert_gltf(&asset("filter.gltf"), &options).unwrap();
+        output.model.meshes[0].verts.len() / 6
+    };
+
+    // "helpers" has `"psx_export": false`, which also skips its child
+    assert_eq!(convert(&[], &[]), 3);
+    assert_eq!(convert(&[], &["*_ref", "COL_*"]), 1);
+    assert_eq!(convert(&["level", "helper*"], &[]), 1);
+}
+
+#[test]
+fn convert_instances() {
+    // Two nodes share the crate mesh, the second one is moved, turned 90 degrees around Y and scaled by 2
+    let options = ConvertOptions {
+        instancing: true,
+        ..Default::default()
+    };
+    let output = convert_gltf(&asset("instances.gltf"), &options).unwrap();
+    assert!(output.warnings.is_empty());
+    assert_eq!(output.model.meshes.len(), 2);
+    assert!(output
+        .model
+        .meshes
+        .iter()
+        .all(|mesh| mesh.verts.iter().all(|v| v.pos_z == 0)));
+
+    let instances = &output.model.instances;
+    let submeshes: Vec<u32> = instances.iter().map(|i| i.submesh_index).collect();
+    assert_eq!(submeshes, [0, 0, 1]);
+    assert_eq!(instances[0].transform.position, [0, 0, 1024]);
+    assert_eq!(
+        instances[0].transform.rotation,
+        [4096, 0, 0, 0, 4096, 0, 0, 0, 4096]
+    );
+
+    // With Y pointing down, X flips as well
+    assert_eq!(instances[1].transform.position, [-2048, 0, 0]);
+    assert_eq!(
+        instances[1].transform.rotation,
+        [0, 0, -4096, 0, 4096, 0, 4096, 0, 0]
+    );
+    assert_eq!(instances[1].transform.scale, [8192; 3]);
+
+    // The instance table has to pass the inspector
+    let msh = output.model.to_bytes().unwrap();
+    assert!(debug_msh(&mut Cursor::new(msh)).unwrap());
+}
+
+#[test]
+fn export_entities() {
+    // Every node without a mesh is an entity, including "pickups", which only groups its child
+    let output = convert_gltf(&asset("entities.gltf"), &ConvertOptions::default()).unwrap();
+    let entities = &output.entities.entities;
+    let names: Vec<&str> = entities.iter().map(|entity| entity.name.as_str()).collect();
+    assert_eq!(names, ["spawn_player", "pickups", "pickup_01"]);
+
+    // With Y pointing down, X and Y flip
+    assert_eq!(entities[0].transform.position, [-1024, 0, 2048]);
+    assert_eq!(entities[2].transform.position, [0, -512, 0]);
+
+    // Arrays can't be exported, and `psx_export` is only for the converter. Floats are always fixed point,
+    // even without a fraction, so a key has the same type on every entity
+    assert_eq!(output.warnings.len(), 1);
+    assert!(output.warnings[0].contains("'path'"));
+    assert!(entities[1].properties.is_empty());
+    let properties: Vec<(&str, &PropertyValue)> = entities[0]
+        .properties
+        .iter()
+        .map(|property| (property.key.as_str(), &property.value))
+        .collect();
+    assert_eq!(
+        properties,
+        [
+            ("active", &PropertyValue::Bool(true)),
+            ("health", &PropertyValue::Fixed(409600)),
+            ("speed", &PropertyValue::Fixed(6144)),
+            ("team", &PropertyValue::Int(2)),
+            ("type", &PropertyValue::String(String::from("spawn"))),
+        ]
+    );
+
+    // The entity file has to pass the inspector
+    let ent = output.entities.to_bytes().unwrap();
+    assert!(debug_ent(&mut Cursor::new(ent)).unwrap());
+
+    // Only export the selected keys
+    let options = ConvertOptions {
+        entity_properties: vec![String::from("ty*")],
+        ..Default::default()
+    };
+    let output = convert_gltf(&asset("entities.gltf"), &options).unwrap();
+    assert!(output.warnings.is_empty());
+    assert!(output
+        .entities
+        .entities
+        .iter()
+        .flat_map(|entity| &entity.properties)
+        .all(|property| property.key == "type"));
+}
+
+#[test]
+fn rigid_skinning() {
+    // Two quads next to each other, one on each bone. The skin lists the child bone first
+    let options = ConvertOptions {
+        partition: Partition::None,
+        ..Default::default()
+    };
+    let output = convert_gltf(&asset("skinned.gltf"), &options).unwrap();
+    assert!(output.warnings.is_empty());
+    let names: Vec<Option<&str>> = output
+        .model
+        .meshes
+        .iter()
+        .map(|mesh| mesh.name.as_deref())
+        .collect();
+    assert_eq!(names, [Some("root"), Some("arm")]);
+
+    // Both quads end up in the local space of their bone, which puts them at the same spot.
+    // The transform of the skinned node itself is ignored
+    let meshes = &output.model.meshes;
+    assert_eq!(triangles(&meshes[0].verts), triangles(&meshes[1].verts));
+    assert!(meshes[0].verts.iter().all(|v| v.pos_z == 0));
+
+    // Parents come first, and the bind pose is relative to the parent
+    let bones = &output.skeleton.bones;
+    let names: Vec<&str> = bones.iter().map(|bone| bone.name.as_str()).collect();
+    assert_eq!(names, ["root", "arm"]);
+    assert_eq!(bones[0].parent, None);
+    assert_eq!(bones[1].parent, Some(0));
+    assert_eq!(bones[0].submesh_index, Some(0));
+    assert_eq!(bones[1].submesh_index, Some(1));
+    assert_eq!(bones[0].transform.position, [0, 0, 0]);
+    assert_eq!(bones[1].transform.position, [-1024, 0, 0]);
+
+    // The bones don't show up as entities
+    assert!(output.entities.entities.is_empty());
+
+    // The skeleton has to pass the inspector
+    let skl = output.skeleton.to_bytes().unwrap();
+    assert!(debug_skl(&mut Cursor::new(skl)).unwrap());
+}
+
+#[test]
+fn exclude_a_joint() {
+    // Excluding the "arm" joint leaves it out of the skeleton, together with the quad that's skinned to it.
+    // Including only the skinned "body" still keeps the joints it needs
+    let convert = |include: &[&str], exclude: &[&str]| {
+        let options = ConvertOptions {
+            partition: Partition::None,
+            node_filter: NodeFilter {
+                include: include.iter().map(|pattern| pattern.to_string()).collect(),
+                exclude: exclude.iter().map(|pattern| pattern.to_string()).collect(),
+            },
+            ..Default::default()
+        };
+        convert_gltf(&asset("skinned.gltf"), &options).unwrap()
+    };
+    let output = convert(&[], &["arm"]);
+    let bones: Vec<&str> = output
+        .skeleton
+        .bones
+        .iter()
+        .map(|bone| bone.name.as_str())
+        .collect();
+    assert_eq!(bones, ["root"]);
+    let meshes = &output.model.meshes;
+    assert_eq!(meshes.len(), 1);
+    assert_eq!(meshes[0].name.as_deref(), Some("root"));
+    assert_eq!(meshes[0].verts.len(), 6);
+
+    let output = convert(&["body"], &[]);
+    assert_eq!(output.skeleton.bones.len(), 2);
+    assert_eq!(output.model.meshes.len(), 2);
+}
+
+#[test]
+fn mesh_on_a_bone() {
+    // The same skin as before, with a static "sword" quad on the "arm" joint, one unit along X
+    let options = ConvertOptions {
+        partition: Partition::None,
+        ..Default::default()
+    };
+    let output = convert_gltf(&asset("attached.gltf"), &options).unwrap();
+    assert!(output.warnings.is_empty());
+    let meshes = &output.model.meshes;
+    let names: Vec<Option<&str>> = meshes.iter().map(|mesh| mesh.name.as_deref()).collect();
+    assert_eq!(names, [None, Some("root"), Some("arm")]);
+
+    // The sword stays in model space, and doesn't end up in the submesh of the bone
+    assert_eq!(meshes[0].verts.len(), 6);
+    assert!(meshes[0].verts.iter().all(|v| v.pos_x <= -1024));
+    assert_eq!(triangles(&meshes[1].verts), triangles(&meshes[2].verts));
+    let bones = &output.skeleton.bones;
+    assert_eq!(bones[0].submesh_index, Some(1));
+    assert_eq!(bones[1].submesh_index, Some(2));
+}
+
+#[test]
+fn resample_animations() {
+    // "wave" turns the arm 90 degrees around Z and steps the root up halfway through,
+    // "bob" moves the root along a cubic spline
+    let options = ConvertOptions {
+        frame_rate: 2.0,
+        ..Default::default()
+    };
+    let output = convert_gltf(&asset("animated.gltf"), &options).unwrap();
+    assert!(output.warnings.is_empty());
+    let clips = &output.animations.clips;
+    let names: Vec<&str> = clips.iter().map(|clip| clip.name.as_str()).collect();
+    assert_eq!(names, ["wave", "bob"]);
+    assert_eq!(clips[0].n_frames, 3);
+
+    // The tracks are in node order, and refer to the bones in the skeleton
+    let wave = &clips[0].tracks;
+    let tracks: Vec<(&str, Option<u32>)> = wave
+        .iter()
+        .map(|track| (track.name.as_str(), track.bone_index))
+        .collect();
+    assert_eq!(tracks, [("root", Some(0)), ("arm", Some(1))]);
+
+    // Linear rotations are interpolated, and the rest of the node's transform stays the same
+    let arm = &wave[1].frames;
+    assert!(arm.iter().all(|frame| frame.position == [-1024, 0, 0]));
+    assert_eq!(arm[0].rotation, [4096, 0, 0, 0, 4096, 0, 0, 0, 4096]);
+    assert_eq!(arm[1].rotation, [2896, -2896, 0, 2896, 2896, 0, 0, 0, 4096]);
+    assert_eq!(arm[2].rotation, [0, -4096, 0, 4096, 0, 0, 0, 0, 4096]);
+
+    // Steps jump to the next value at its keyframe
+    let root: Vec<[i32; 3]> = wave[0].frames.iter().map(|frame| frame.position).collect();
+    assert_eq!(root, [[0, 0, 0], [0, -1024, 0], [0, -1024, 0]]);
+
+    // With flat tangents, halfway through the spline is halfway between the values
+    let root: Vec<[i32; 3]> = clips[1].tracks[0]
+        .frames
+        .iter()
+        .map(|frame| frame.position)
+        .collect();
+    assert_eq!(root, [[0, 0, 0], [0, -1024, 0], [0, -2048, 0]]);
+
+    // The animation file has to pass the inspector
+    let anm = output.animations.to_bytes().unwrap();
+    assert!(debug_anm(&mut Cursor::new(anm)).unwrap());
+}
+
+#[test]
+fn animate_bones_under_an_armature() {
+    // The skin from before, under an "Armature" node that's turned 90 degrees around X and scaled by 2,
+    // with a "bend" node that's not a joint between the root and the arm
+    let options = ConvertOptions {
+        frame_rate: 2.0,
+        ..Default::default()
+    };
+    let output = convert_gltf(&asset("armature.gltf"), &options).unwrap();
+    assert!(output.warnings.is_empty());
+    let bones = &output.skeleton.bones;
+    let wave = &output.animations.clips[0].tracks;
+    let tracks: Vec<(&str, Option<u32>)> = wave
+        .iter()
+        .map(|track| (track.name.as_str(), track.bone_index))
+        .collect();
+    assert_eq!(
+        tracks,
+        [("root", Some(0)), ("arm", Some(1)), ("bend", None)]
+    );
+
+    // The first frame of each bone is its bind pose, so the root includes the armature, and the arm the bend
+    assert_eq!(wave[0].frames[0], bones[0].transform);
+    assert_eq!(wave[1].frames[0], bones[1].transform);
+    assert_eq!(bones[0].transform.scale, [8192, 8192, 8192]);
+    assert!(wave[1]
+        .frames
+        .iter()
+        .all(|frame| frame.position == [-2048, 0, 0]));
+
+    // The root steps up along its own Y axis, which the armature turns into Z
+    let root: Vec<[i32; 3]> = wave[0].frames.iter().map(|frame| frame.position).collect();
+    assert_eq!(root, [[0, 0, 0], [0, 0, 2048], [0, 0, 2048]]);
+}
+
+#[test]
+fn animate_mesh_nodes() {
+    // "open" slides the "door" triangle from 2 to 4 units along X, and its "handle" child with it.
+    // The "ground" triangle doesn't move
+    let options = ConvertOptions {
+        partition: Partition::None,
+        frame_rate: 2.0,
+        ..Default::default()
+    };
+    let output = convert_gltf(&asset("door.gltf"), &options).unwrap();
+    assert!(output.warnings.is_empty());
+
+    // The animated nodes keep their own submesh in local space, placed by the instance table
+    let meshes = &output.model.meshes;
+    let names: Vec<Option<&str>> = meshes.iter().map(|mesh| mesh.name.as_deref()).collect();
+    assert_eq!(names, [None, Some("door"), Some("handle")]);
+    assert_eq!(triangles(&meshes[1].verts), triangles(&meshes[2].verts));
+    assert!(meshes[1]
+        .verts
+        .iter()
+        .all(|v| (-1024..=0).contains(&v.pos_x)));
+    let instances: Vec<(u32, [i32; 3])> = output
+        .model
+        .instances
+        .iter()
+        .map(|instance| (instance.submesh_index, instance.transform.position))
+        .collect();
+    assert_eq!(instances, [(1, [-2048, 0, 0]), (2, [-2048, -1024, 0])]);
+
+    // Their tracks refer to those submeshes, and move them in model space
+    let tracks = &output.animations.clips[0].tracks;
+    let names: Vec<(&str, Option<u32>, Option<u32>)> = tracks
+        .iter()
+        .map(|track| (track.name.as_str(), track.bone_index, track.submesh_index))
+        .collect();
+    assert_eq!(names, [("door", None, Some(1)), ("handle", None, Some(2))]);
+    let handle: Vec<[i32; 3]> = tracks[1]
+        .frames
+        .iter()
+        .map(|frame| frame.position)
+        .collect();
+    assert_eq!(
+        handle,
+        [[-2048, -1024, 0], [-3072, -1024, 0], [-4096, -1024, 0]]
+    );
+    let anm = output.animations.to_bytes().unwrap();
+    assert!(debug_anm(&mut Cursor::new(anm)).unwrap());
+}
+
+#[test]
+fn warn_once_per_problem() {
+    // The "lid" entity is too big and too far away on every frame, which warns about its scale and position in turn
+    let bin: Vec<u8> = [0.0f32, 1.0, 1.0e5, 0.0, 0.0, 1.0e5, 0.0, 0.0]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    let json = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "name": "lid", "translation": [1e5, 0, 0], "scale": [10, 10, 10] }],
+        "animations": [{
+            "name": "open",
+            "channels": [{ "sampler": 0, "target": { "node": 0, "path": "translation" } }],
+            "samplers": [{ "input": 0, "output": 1 }]
+        }],
+        "buffers": [{ "byteLength": 32 }],
+        "bufferViews": [{ "buffer": 0, "byteOffset": 0, "byteLength": 32 }],
+        "accessors": [
+            { "bufferView": 0, "byteOffset": 0, "componentType": 5126, "count": 2, "type": "SCALAR",
+              "min": [0], "max": [1] },
+            { "bufferView": 0, "byteOffset": 8, "componentType": 5126, "count": 2, "type": "VEC3" }
+        ]
+    }"#;
+    let options = ConvertOptions {
+        scale: Scale::Fixed(60000.0),
+        frame_rate: 2.0,
+        ..Default::default()
+    };
+    let output = convert_gltf_slice(&glb(json, &bin), &options).unwrap();
+    assert_eq!(output.animations.clips[0].n_frames, 3);
+    let animation: Vec<&String> = output
+        .warnings
+        .iter()
+        .filter(|warning| warning.starts_with("animation"))
+        .collect();
+    assert_eq!(animation.len(), 2);
+    assert!(animation[0].contains("scale"));
+    assert!(animation[1].contains("position"));
+}
+
+#[test]
+fn bake_morph_targets() {
+    // "flag" has an "up" target that raises its last vertex, and a "right" target that moves its
+    // second vertex along X. "wave" blends the weights from 0 to 1 and 0.5 over a second
+    let options = ConvertOptions {
+        frame_rate: 2.0,
+        ..Default::default()
+    };
+    let output = convert_gltf(&asset("morph.gltf"), &options).unwrap();
+    assert!(output.warnings.is_empty());
+
+    // The node with morph targets gets its own submesh, after the partitioned ones
+    let meshes = &output.model.meshes;
+    assert_eq!(meshes.len(), 2);
+    assert_eq!(meshes[1].name.as_deref(), Some("flag"));
+
+    // One sequence per target, then the clip, with an offset for every vertex in the submesh's order
+    let sequences = &output.vertex_animations.sequences;
+    let names: Vec<(&str, u32, bool)> = sequences
+        .iter()
+        .map(|sequence| {
+            (
+                sequence.name.as_str(),
+                sequence.submesh_index,
+                sequence.animated,
+            )
+        })
+        .collect();
+    assert_eq!(
+        names,
+        [("up", 1, false), ("right", 1, false), ("wave", 1, true)]
+    );
+    assert_eq!(
+        sequences[0].frames,
+        [vec![[0, 0, 0], [0, 0, 0], [0, -1024, 0]]]
+    );
+    assert_eq!(
+        sequences[1].frames,
+        [vec![[0, 0, 0], [-1024, 0, 0], [0, 0, 0]]]
+    );
+    assert_eq!(
+        sequences[2].frames,
+        [
+            vec![[0, 0, 0], [0, 0, 0], [0, 0, 0]],
+            vec![[0, 0, 0], [-256, 0, 0], [0, -512, 0]],
+            vec![[0, 0, 0], [-512, 0, 0], [0, -1024, 0]],
+        ]
+    );
+
+    // A clip that only animates weights doesn't end up in the animation file
+    assert!(output.animations.clips.is_empty());
+
+    // The vertex animation file has to pass the inspector
+    let vta = output.vertex_animations.to_bytes().unwrap();
+    assert!(debug_vta(&mut Cursor::new(vta)).unwrap());
+}
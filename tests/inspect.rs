@@ -0,0 +1,126 @@
+use std::{fs::File, io::Cursor, path::PathBuf};
+
+use gltf2psx::{
+    inspect::{debug_msh, debug_txc},
+    psx_structs::{MeshDesc, MeshPSX, TextureCellPSX, VertexPSX, MSH_HEADER_SIZE, NO_TABLE},
+    ModelPSX, TextureCollectionPSX,
+};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("gltf2psx_{}_{name}", std::process::id()))
+}
+
+fn test_vertex(pos_x: i16, texture_id: u8) -> VertexPSX {
+    VertexPSX {
+        pos_x,
+        pos_y: -pos_x,
+        pos_z: 100,
+        color_r: 255,
+        color_g: 128,
+        color_b: 0,
+        tex_u: 0,
+        tex_v: 255,
+        texture_id,
+    }
+}
+
+fn test_model() -> ModelPSX {
+    let mut model = ModelPSX::new();
+    for submesh in 0..3 {
+        let mut mesh = MeshPSX::new();
+        for i in 0..3 * (submesh + 1) {
+            mesh.verts.push(test_vertex(i as i16 * 64, submesh as u8));
+        }
+        model.meshes.push(mesh);
+    }
+    model
+}
+
+fn test_texture_collection() -> TextureCollectionPSX {
+    let mut txc = TextureCollectionPSX::new();
+    for i in 0..2u8 {
+        txc.texture_cells.push(TextureCellPSX {
+            texture_data: vec![i * 0x11; 64 * 64 / 2],
+            palette: (0..16 * 16).map(|color| color as u16).collect(),
+            texture_width: 64,
+            texture_height: 64,
+            avg_color: 0xFF808080,
+        });
+        txc.texture_names.push(format!("material{i}"));
+    }
+    txc
+}
+
+#[test]
+fn inspect_saved_msh() {
+    let path = temp_path("inspect.msh");
+    test_model().save(&path).unwrap();
+    let result = debug_msh(&mut File::open(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.unwrap());
+}
+
+#[test]
+fn inspect_saved_txc() {
+    let path = temp_path("inspect.txc");
+    test_texture_collection().save(&path).unwrap();
+    let result = debug_txc(&mut File::open(&path).unwrap(), None);
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.unwrap());
+}
+
+#[test]
+fn txc_without_name_table() {
+    // The texture names aren't written, so the offset to the name table says there is none
+    let txc = test_texture_collection().to_bytes().unwrap();
+    assert_eq!(txc[20..24], NO_TABLE.to_le_bytes());
+    assert!(debug_txc(&mut Cursor::new(txc), None).unwrap());
+}
+
+#[test]
+fn extract_textures_from_txc() {
+    let out_dir = temp_path("extract");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let bytes = test_texture_collection().to_bytes().unwrap();
+    let result = debug_txc(&mut Cursor::new(bytes), Some(&out_dir));
+    let extracted =
+        [out_dir.join("texture0.png"), out_dir.join("texture1.png")].map(|path| path.exists());
+    std::fs::remove_dir_all(&out_dir).unwrap();
+    assert!(result.unwrap());
+    assert_eq!(extracted, [true, true]);
+}
+
+#[test]
+fn inspect_msh_with_many_vertices() {
+    // More vertices than fit in 16 bits, spread over two submeshes
+    let mut model = ModelPSX::new();
+    for n_vertices in [3 * 20000, 3 * 10000] {
+        let mut mesh = MeshPSX::new();
+        mesh.verts = vec![test_vertex(0, 0); n_vertices];
+        model.meshes.push(mesh);
+    }
+    let msh = model.to_bytes().unwrap();
+    let mesh_desc = MeshDesc::from_bytes(&msh[MSH_HEADER_SIZE + MeshDesc::SIZE..]);
+    assert_eq!(mesh_desc.vertex_start, 60000);
+    assert_eq!(mesh_desc.n_vertices, 30000);
+    assert!(debug_msh(&mut Cursor::new(msh)).unwrap());
+}
+
+#[test]
+fn reject_wrong_magic() {
+    let mut msh = test_model().to_bytes().unwrap();
+    let txc = test_texture_collection().to_bytes().unwrap();
+    assert!(!debug_msh(&mut Cursor::new(txc.clone())).unwrap());
+    assert!(!debug_txc(&mut Cursor::new(msh.clone()), None).unwrap());
+
+    // Corrupt the number of submeshes so the descriptions run past the end of the file
+    msh[8..12].copy_from_slice(&1000u32.to_le_bytes());
+    assert!(!debug_msh(&mut Cursor::new(msh)).unwrap());
+}
+
+#[test]
+fn reject_truncated_txc() {
+    let mut txc = test_texture_collection().to_bytes().unwrap();
+    txc.truncate(txc.len() - 1024);
+    assert!(!debug_txc(&mut Cursor::new(txc), None).unwrap());
+}